@@ -0,0 +1,290 @@
+//! Async transmit and receive, driven by the FdCan interrupts.
+//!
+//! The futures in this module register a [`Waker`] and are woken from [`on_interrupt`], which
+//! has to be called from the FDCAN interrupt handler(s) of the instance. The HAL (or the
+//! application) provides the storage for the wakers by implementing [`AsyncInstance`].
+//!
+//! The interrupts in [`INTERRUPTS`] have to be enabled, and routed to an enabled interrupt
+//! line, before any of the futures can make progress:
+//!
+//! ```ignore
+//! can.enable_interrupts(fdcan::asynch::INTERRUPTS);
+//! can.enable_interrupt_line(InterruptLine::_0, true);
+//! ```
+//!
+//! The receive futures wait for RxFifo0NewMsg/RxFifo1NewMsg, `transmit_async` for TxComplete,
+//! `flush_async` for TxEmpty and `wait_bus_off` for BusOff. TxComplete is only raised for Tx
+//! buffers with their transmission interrupt enabled, which `enable_interrupts` does for all
+//! Tx buffers of the current Message RAM layout. Enable it after the layout is configured.
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use critical_section::Mutex;
+
+use crate::frame::{Frame, RxFrameInfo, TxFrameHeader};
+use crate::interrupt::Interrupts;
+use crate::{FdCanControl, FifoNr, Instance, ReceiveOverrun, Rx, Tx};
+
+/// The interrupts that are serviced by [`on_interrupt`]
+pub const INTERRUPTS: Interrupts = Interrupts::from_bits_truncate(
+    Interrupts::RX_FIFO0_NEW_MSG.bits()
+        | Interrupts::RX_FIFO1_NEW_MSG.bits()
+        | Interrupts::TX_COMPLETE.bits()
+        | Interrupts::TX_EMPTY.bits()
+        | Interrupts::BUS_OFF.bits(),
+);
+
+/// Storage for a single [`Waker`], shared with the interrupt handler
+pub struct WakerSlot {
+    waker: Mutex<RefCell<Option<Waker>>>,
+}
+impl WakerSlot {
+    /// Creates an empty slot
+    pub const fn new() -> Self {
+        Self {
+            waker: Mutex::new(RefCell::new(None)),
+        }
+    }
+
+    /// Registers `waker` to be woken by the next call to [`WakerSlot::wake`]
+    pub fn register(&self, waker: &Waker) {
+        critical_section::with(|cs| {
+            let mut slot = self.waker.borrow_ref_mut(cs);
+            match slot.as_ref() {
+                Some(w) if w.will_wake(waker) => {}
+                _ => *slot = Some(waker.clone()),
+            }
+        });
+    }
+
+    /// Wakes the registered waker, if any
+    pub fn wake(&self) {
+        let waker = critical_section::with(|cs| self.waker.borrow_ref_mut(cs).take());
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+impl Default for WakerSlot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The wakers of one FdCan instance
+pub struct InterruptWakers {
+    rx_fifo: [WakerSlot; 2],
+    tx_complete: WakerSlot,
+    tx_empty: WakerSlot,
+    bus_off: WakerSlot,
+}
+impl InterruptWakers {
+    /// Creates a set of empty wakers, to be placed in a `static`
+    pub const fn new() -> Self {
+        Self {
+            rx_fifo: [WakerSlot::new(), WakerSlot::new()],
+            tx_complete: WakerSlot::new(),
+            tx_empty: WakerSlot::new(),
+            bus_off: WakerSlot::new(),
+        }
+    }
+}
+impl Default for InterruptWakers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An FdCan instance that can be used with the async API.
+///
+/// ```ignore
+/// static FDCAN1_WAKERS: InterruptWakers = InterruptWakers::new();
+///
+/// impl AsyncInstance for Fdcan1 {
+///     fn wakers() -> &'static InterruptWakers {
+///         &FDCAN1_WAKERS
+///     }
+/// }
+/// ```
+pub trait AsyncInstance: Instance {
+    /// Wakers of this instance. Each instance must return its own set.
+    fn wakers() -> &'static InterruptWakers;
+}
+
+/// Interrupt handler entry point.
+///
+/// Reads the interrupt register, wakes the futures waiting on the events in [`INTERRUPTS`] and
+/// clears those flags. Other interrupt flags are left untouched.
+pub fn on_interrupt<I: AsyncInstance>() {
+    // Safety: Only the interrupt register is accessed, flags are cleared by writing ones.
+    let can = unsafe { &*I::REGISTERS };
+    let wakers = I::wakers();
+
+    let ir = Interrupts::from_bits_truncate(can.ir.read().bits()) & INTERRUPTS;
+    can.ir.write(|w| unsafe { w.bits(ir.bits()) });
+
+    if ir.contains(Interrupts::RX_FIFO0_NEW_MSG) {
+        wakers.rx_fifo[0].wake();
+    }
+    if ir.contains(Interrupts::RX_FIFO1_NEW_MSG) {
+        wakers.rx_fifo[1].wake();
+    }
+    if ir.contains(Interrupts::TX_COMPLETE) {
+        wakers.tx_complete.wake();
+    }
+    if ir.contains(Interrupts::TX_EMPTY) {
+        wakers.tx_empty.wake();
+    }
+    if ir.contains(Interrupts::BUS_OFF) {
+        wakers.bus_off.wake();
+    }
+}
+
+/// `core::future::poll_fn` is not available on our MSRV
+struct PollFn<F> {
+    f: F,
+}
+impl<F> Unpin for PollFn<F> {}
+impl<T, F> Future for PollFn<F>
+where
+    F: FnMut(&mut Context<'_>) -> Poll<T>,
+{
+    type Output = T;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        (self.f)(cx)
+    }
+}
+fn poll_fn<T, F>(f: F) -> PollFn<F>
+where
+    F: FnMut(&mut Context<'_>) -> Poll<T>,
+{
+    PollFn { f }
+}
+
+fn nb_to_poll<T>(result: nb::Result<T, Infallible>) -> Poll<T> {
+    match result {
+        Ok(t) => Poll::Ready(t),
+        Err(nb::Error::WouldBlock) => Poll::Pending,
+        Err(nb::Error::Other(never)) => match never {},
+    }
+}
+
+impl<I, MODE> Tx<I, MODE>
+where
+    I: AsyncInstance,
+{
+    /// Puts a CAN frame in a transmit mailbox, waiting for one to become available.
+    ///
+    /// As [`Tx::transmit`], a lower priority frame may be replaced when all mailboxes are full.
+    /// That frame is returned, so it can be sent again later.
+    ///
+    /// Woken by the TxComplete interrupt, see the [module documentation](self).
+    pub async fn transmit_async(
+        &mut self,
+        frame: TxFrameHeader,
+        buffer: &[u8],
+    ) -> Option<Frame> {
+        poll_fn(|cx| {
+            I::wakers().tx_complete.register(cx.waker());
+            nb_to_poll(
+                self.transmit_preserve(frame, buffer, &mut |_, h, d| {
+                    Frame::from_tx(h, d)
+                }),
+            )
+        })
+        .await
+    }
+
+    /// Waits until no frame is pending for transmission.
+    pub async fn flush_async(&mut self) {
+        poll_fn(|cx| {
+            I::wakers().tx_empty.register(cx.waker());
+            if self.is_idle() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
+
+impl<I, MODE, FIFONR> Rx<I, MODE, FIFONR>
+where
+    I: AsyncInstance,
+    FIFONR: FifoNr,
+{
+    /// Waits for a received frame, see [`Rx::receive`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is smaller than the header length.
+    pub async fn receive_async(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> ReceiveOverrun<RxFrameInfo> {
+        poll_fn(|cx| {
+            I::wakers().rx_fifo[FIFONR::NR].register(cx.waker());
+            nb_to_poll(self.receive(buffer))
+        })
+        .await
+    }
+
+    /// Returns a stream of received frames
+    pub fn stream(&mut self) -> RxStream<'_, I, MODE, FIFONR> {
+        RxStream { rx: self }
+    }
+}
+
+/// A never ending stream of frames received in a FIFO.
+///
+/// `poll_next` has the same signature as `futures::Stream::poll_next`, so this type can be
+/// wrapped into a `Stream` without further dependencies on this crate.
+pub struct RxStream<'a, I, MODE, FIFONR>
+where
+    FIFONR: FifoNr,
+{
+    rx: &'a mut Rx<I, MODE, FIFONR>,
+}
+impl<'a, I, MODE, FIFONR> RxStream<'a, I, MODE, FIFONR>
+where
+    I: AsyncInstance,
+    FIFONR: FifoNr,
+{
+    /// Attempts to pull out the next frame, registering the current task for wakeup if no frame
+    /// is available yet. Never returns `Ready(None)`.
+    pub fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<ReceiveOverrun<Frame>>> {
+        I::wakers().rx_fifo[FIFONR::NR].register(cx.waker());
+        nb_to_poll(self.get_mut().rx.receive_frame()).map(Some)
+    }
+
+    /// Waits for the next frame
+    pub async fn next(&mut self) -> Option<ReceiveOverrun<Frame>> {
+        poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl<I, MODE> FdCanControl<I, MODE>
+where
+    I: AsyncInstance,
+{
+    /// Waits until the FdCan enters the Bus_Off state.
+    pub async fn wait_bus_off(&mut self) {
+        poll_fn(|cx| {
+            I::wakers().bus_off.register(cx.waker());
+            if self.read_psr().bo().bit_is_set() {
+                Poll::Ready(())
+            } else {
+                Poll::Pending
+            }
+        })
+        .await
+    }
+}
@@ -0,0 +1,526 @@
+//! Software queues layered over the hardware Tx buffers and Rx FIFOs.
+//!
+//! The Message RAM only holds a few Tx buffers. [`BufferedTx`] keeps the frames that do not fit in
+//! a priority queue and moves them to the Tx buffers as they become available. The queue is
+//! refilled from the TxComplete interrupt, which is raised each time a Tx buffer is sent.
+//! Enabling it with `enable_interrupt` also enables the transmission interrupt of the Tx buffers
+//! (TXBTIE), so do this after the Message RAM layout is configured. TxEmpty works as well, but
+//! only refills the Tx buffers once all of them were sent:
+//!
+//! ```ignore
+//! static TX: Mutex<RefCell<Option<BufferedTx<Fdcan1, NormalOperationMode, 16>>>> = ...;
+//!
+//! can.enable_interrupt(Interrupt::TxComplete);
+//! can.enable_interrupt_line(InterruptLine::_0, true);
+//!
+//! #[interrupt]
+//! fn FDCAN1_IT0() {
+//!     critical_section::with(|cs| {
+//!         // clear the TxComplete flag, then
+//!         TX.borrow_ref_mut(cs).as_mut().unwrap().refill();
+//!     });
+//! }
+//! ```
+//!
+//! Likewise, `BufferedRx` drains the Rx FIFOs into ring buffers from the RxFifoNNewMsg
+//! interrupts, so frames are not lost while the application is busy. It is shared with the
+//! interrupt handler through a critical section and requires the `buffered` feature:
+//!
+//! ```ignore
+//! static RX: BufferedRx<32> = BufferedRx::new();
+//!
+//! #[interrupt]
+//! fn FDCAN1_IT0() {
+//!     // clear the RxFifo0NewMsg flag, then
+//!     RX.drain(&mut rx0);
+//! }
+//!
+//! while let Some(received) = RX.pop() { ... }
+//! ```
+
+#[cfg(feature = "buffered")]
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+#[cfg(feature = "buffered")]
+use critical_section::Mutex;
+
+#[cfg(feature = "buffered")]
+use crate::filter::RxFifo;
+#[cfg(feature = "buffered")]
+use crate::frame::RxFrameInfo;
+use crate::frame::{Frame, FramePriority, TxFrameHeader};
+#[cfg(feature = "buffered")]
+use crate::{FifoNr, ReceiveOverrun, Rx};
+use crate::{Instance, Tx};
+
+#[derive(Clone, Copy)]
+struct TxEntry {
+    frame: Frame,
+    marker: Option<u8>,
+}
+impl TxEntry {
+    #[inline]
+    fn priority(&self) -> FramePriority {
+        self.frame.priority()
+    }
+}
+
+/// Queued frames, sorted highest priority first
+struct TxQueue<const N: usize> {
+    entries: [Option<TxEntry>; N],
+    len: usize,
+}
+impl<const N: usize> TxQueue<N> {
+    fn new() -> Self {
+        Self {
+            entries: [None; N],
+            len: 0,
+        }
+    }
+
+    #[inline]
+    fn first(&self) -> Option<TxEntry> {
+        self.entries[..self.len].first().copied().flatten()
+    }
+
+    /// Queues a new frame after the frames of equal priority
+    fn push(&mut self, entry: TxEntry) {
+        let priority = entry.priority();
+        let idx = self.position(|p| p < priority);
+        self.insert(idx, entry);
+    }
+
+    /// Puts back a frame replaced in the Tx buffers. It is queued before the frames of equal
+    /// priority, as it was queued earlier.
+    fn requeue(&mut self, entry: TxEntry) {
+        let priority = entry.priority();
+        let idx = self.position(|p| p <= priority);
+        self.insert(idx, entry);
+    }
+
+    fn remove_first(&mut self) {
+        self.entries[..self.len].rotate_left(1);
+        self.len -= 1;
+        self.entries[self.len] = None;
+    }
+
+    /// Index of the first queued frame whose priority matches `f`, or the end of the queue
+    fn position<F>(&self, f: F) -> usize
+    where
+        F: Fn(FramePriority) -> bool,
+    {
+        self.entries[..self.len]
+            .iter()
+            .flatten()
+            .position(|e| f(e.priority()))
+            .unwrap_or(self.len)
+    }
+
+    fn insert(&mut self, idx: usize, entry: TxEntry) {
+        self.entries[idx..=self.len].rotate_right(1);
+        self.entries[idx] = Some(entry);
+        self.len += 1;
+    }
+}
+
+/// A transmit queue of up to `N` frames in front of the Tx buffers
+///
+/// Queued frames are sorted by their [`FramePriority`] and moved to the Tx buffers highest
+/// priority first. Frames of equal priority are transmitted in the order they were queued.
+///
+/// When all Tx buffers are pending, a queued frame replaces a pending frame of lower priority,
+/// which is put back into the queue. A frame is thus never kept waiting by a lower priority frame.
+/// This requires [`TxBufferMode::Queue`](crate::config::TxBufferMode::Queue); in FIFO mode, the
+/// Tx buffers are only refilled as they become free.
+pub struct BufferedTx<I, MODE, const N: usize>
+where
+    I: Instance,
+{
+    tx: Tx<I, MODE>,
+    queue: TxQueue<N>,
+}
+
+impl<I, MODE, const N: usize> BufferedTx<I, MODE, N>
+where
+    I: Instance,
+{
+    /// Creates an empty queue in front of `tx`
+    pub fn new(tx: Tx<I, MODE>) -> Self {
+        Self {
+            tx,
+            queue: TxQueue::new(),
+        }
+    }
+
+    /// Returns the transmitter. Frames still in the queue are dropped.
+    pub fn free(self) -> Tx<I, MODE> {
+        self.tx
+    }
+
+    /// Number of frames in the queue, not counting the frames in the Tx buffers
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.queue.len
+    }
+
+    /// Returns `true` if no frames are waiting in the queue
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.queue.len == 0
+    }
+
+    /// Returns `true` if no more frames can be queued
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.queue.len == N
+    }
+
+    /// Drops all frames in the queue. Frames in the Tx buffers are not affected.
+    pub fn clear(&mut self) {
+        self.queue = TxQueue::new();
+    }
+
+    /// Queues `frame` for transmission and refills the Tx buffers.
+    ///
+    /// `marker` is passed on to the Tx event of the frame, see [`TxFrameHeader::marker`].
+    /// Returns Err::WouldBlock if the queue is full.
+    pub fn transmit(
+        &mut self,
+        frame: &Frame,
+        marker: Option<u8>,
+    ) -> nb::Result<(), Infallible> {
+        if self.is_full() {
+            self.refill();
+            if self.is_full() {
+                return Err(nb::Error::WouldBlock);
+            }
+        }
+        self.queue.push(TxEntry {
+            frame: *frame,
+            marker,
+        });
+
+        self.refill();
+        Ok(())
+    }
+
+    /// Moves queued frames to the Tx buffers, highest priority first.
+    ///
+    /// Call this from the handler of the TxComplete or TxEmpty interrupt, see the
+    /// [module documentation](self).
+    pub fn refill(&mut self) {
+        while let Some(entry) = self.queue.first() {
+            let header = entry.frame.tx_header(entry.marker);
+            let pending = &mut |_, h: TxFrameHeader, d: &[u32]| TxEntry {
+                frame: Frame::from_tx(h, d),
+                marker: h.marker,
+            };
+            match self
+                .tx
+                .transmit_preserve(header, entry.frame.data(), pending)
+            {
+                Ok(replaced) => {
+                    self.queue.remove_first();
+                    if let Some(replaced) = replaced {
+                        self.queue.requeue(replaced);
+                    }
+                }
+                Err(nb::Error::WouldBlock) => break,
+                Err(nb::Error::Other(never)) => match never {},
+            }
+        }
+    }
+}
+
+/// A frame received by [`BufferedRx`]
+#[cfg(feature = "buffered")]
+#[derive(Clone, Copy, Debug)]
+pub struct BufferedFrame {
+    /// Rx FIFO the frame was received in
+    pub fifo: RxFifo,
+    /// Header of the frame, with its time stamp and the matching filter
+    pub info: RxFrameInfo,
+    /// The frame
+    pub frame: Frame,
+}
+
+#[cfg(feature = "buffered")]
+struct Ring<const N: usize> {
+    frames: [Option<BufferedFrame>; N],
+    head: usize,
+    len: usize,
+    dropped: u32,
+    lost: u32,
+}
+#[cfg(feature = "buffered")]
+impl<const N: usize> Ring<N> {
+    const fn new() -> Self {
+        Self {
+            frames: [None; N],
+            head: 0,
+            len: 0,
+            dropped: 0,
+            lost: 0,
+        }
+    }
+
+    fn push(&mut self, frame: BufferedFrame) {
+        if self.len == N {
+            self.dropped = self.dropped.wrapping_add(1);
+        } else {
+            self.frames[(self.head + self.len) % N] = Some(frame);
+            self.len += 1;
+        }
+    }
+
+    fn pop(&mut self) -> Option<BufferedFrame> {
+        if self.len == 0 {
+            return None;
+        }
+        let frame = self.frames[self.head].take();
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        frame
+    }
+}
+
+/// Ring buffers of up to `N` frames for each Rx FIFO
+///
+/// [`BufferedRx::drain`] moves the frames of an Rx FIFO into its ring buffer and is meant to be
+/// called from the RxFifoNNewMsg interrupt. When a ring buffer is full, newly received frames are
+/// dropped and counted, see [`BufferedRx::dropped`]. Frames lost by the hardware because an Rx
+/// FIFO overran before it was drained are counted separately, see [`BufferedRx::lost`].
+#[cfg(feature = "buffered")]
+pub struct BufferedRx<const N: usize> {
+    rings: Mutex<RefCell<[Ring<N>; 2]>>,
+}
+#[cfg(feature = "buffered")]
+impl<const N: usize> BufferedRx<N> {
+    /// Creates empty ring buffers, to be placed in a `static`
+    pub const fn new() -> Self {
+        Self {
+            rings: Mutex::new(RefCell::new([Ring::new(), Ring::new()])),
+        }
+    }
+
+    /// Moves all frames of the Rx FIFO of `rx` into its ring buffer.
+    ///
+    /// If the Rx FIFO overran, this counts it as lost and clears its message lost flag.
+    pub fn drain<I, MODE, FIFONR>(&self, rx: &mut Rx<I, MODE, FIFONR>)
+    where
+        I: Instance,
+        FIFONR: FifoNr,
+    {
+        let fifo = match FIFONR::NR {
+            0 => RxFifo::Fifo0,
+            _ => RxFifo::Fifo1,
+        };
+        let mut buffer = [0_u8; 64];
+        let mut overrun = false;
+        while let Ok(received) = rx.receive(&mut buffer) {
+            let info = match received {
+                ReceiveOverrun::NoOverrun(info) => info,
+                ReceiveOverrun::Overrun(info) => {
+                    overrun = true;
+                    info
+                }
+            };
+            let frame = BufferedFrame {
+                fifo,
+                info,
+                frame: Frame::from_rx(info, &buffer),
+            };
+            critical_section::with(|cs| {
+                self.rings.borrow_ref_mut(cs)[FIFONR::NR].push(frame)
+            });
+        }
+        if overrun {
+            rx.clear_overrun();
+            critical_section::with(|cs| {
+                let ring = &mut self.rings.borrow_ref_mut(cs)[FIFONR::NR];
+                ring.lost = ring.lost.wrapping_add(1);
+            });
+        }
+    }
+
+    /// Takes the oldest frame, from Rx FIFO 0 first, then from Rx FIFO 1
+    pub fn pop(&self) -> Option<BufferedFrame> {
+        critical_section::with(|cs| {
+            let mut rings = self.rings.borrow_ref_mut(cs);
+            let frame = rings[0].pop();
+            frame.or_else(|| rings[1].pop())
+        })
+    }
+
+    /// Takes the oldest frame received in `fifo`
+    pub fn pop_fifo(&self, fifo: RxFifo) -> Option<BufferedFrame> {
+        critical_section::with(|cs| self.rings.borrow_ref_mut(cs)[fifo as usize].pop())
+    }
+
+    /// Number of buffered frames of `fifo`
+    pub fn len(&self, fifo: RxFifo) -> usize {
+        critical_section::with(|cs| self.rings.borrow_ref(cs)[fifo as usize].len)
+    }
+
+    /// Number of frames of `fifo` that were dropped because its ring buffer was full
+    pub fn dropped(&self, fifo: RxFifo) -> u32 {
+        critical_section::with(|cs| self.rings.borrow_ref(cs)[fifo as usize].dropped)
+    }
+
+    /// Resets the dropped frame count of `fifo`
+    pub fn reset_dropped(&self, fifo: RxFifo) {
+        critical_section::with(|cs| {
+            self.rings.borrow_ref_mut(cs)[fifo as usize].dropped = 0
+        });
+    }
+
+    /// Number of times the Rx FIFO `fifo` overran and lost frames before it was drained.
+    ///
+    /// The hardware does not report how many frames were lost in an overrun, so each overrun
+    /// counts once.
+    pub fn lost(&self, fifo: RxFifo) -> u32 {
+        critical_section::with(|cs| self.rings.borrow_ref(cs)[fifo as usize].lost)
+    }
+
+    /// Resets the overrun count of `fifo`
+    pub fn reset_lost(&self, fifo: RxFifo) {
+        critical_section::with(|cs| {
+            self.rings.borrow_ref_mut(cs)[fifo as usize].lost = 0
+        });
+    }
+}
+#[cfg(feature = "buffered")]
+impl<const N: usize> Default for BufferedRx<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::StandardId;
+
+    fn entry(id: u16, tag: u8) -> TxEntry {
+        TxEntry {
+            frame: Frame::new_data(StandardId::new(id).unwrap(), &[tag]).unwrap(),
+            marker: Some(tag),
+        }
+    }
+
+    fn tags<const N: usize>(queue: &TxQueue<N>) -> [Option<u8>; N] {
+        let mut tags = [None; N];
+        for (tag, entry) in tags.iter_mut().zip(&queue.entries[..queue.len]) {
+            *tag = entry.and_then(|e| e.marker);
+        }
+        tags
+    }
+
+    #[test]
+    fn tx_queue_priority_order() {
+        let mut queue = TxQueue::<5>::new();
+        queue.push(entry(0x100, 1));
+        queue.push(entry(0x200, 2));
+        queue.push(entry(0x100, 3));
+        queue.push(entry(0x080, 4));
+        queue.push(entry(0x100, 5));
+        // Lowest ID first, frames of equal priority in the order they were queued
+        assert_eq!(tags(&queue), [Some(4), Some(1), Some(3), Some(5), Some(2)]);
+
+        let mut sent = [0; 5];
+        for tag in sent.iter_mut() {
+            *tag = queue.first().unwrap().marker.unwrap();
+            queue.remove_first();
+        }
+        assert_eq!(sent, [4, 1, 3, 5, 2]);
+        assert!(queue.first().is_none());
+        assert_eq!(queue.len, 0);
+    }
+
+    #[test]
+    fn tx_queue_requeue() {
+        let mut queue = TxQueue::<4>::new();
+        queue.push(entry(0x100, 1));
+        queue.push(entry(0x100, 2));
+        queue.push(entry(0x300, 3));
+
+        // A frame replaced in the Tx buffers goes before the frames of equal priority
+        queue.requeue(entry(0x100, 0));
+        assert_eq!(tags(&queue), [Some(0), Some(1), Some(2), Some(3)]);
+
+        // Sending the first frame evicts a frame of lower priority, filling the queue again
+        queue.remove_first();
+        queue.requeue(entry(0x200, 4));
+        assert_eq!(tags(&queue), [Some(1), Some(2), Some(4), Some(3)]);
+        queue.remove_first();
+        queue.requeue(entry(0x400, 5));
+        assert_eq!(tags(&queue), [Some(2), Some(4), Some(3), Some(5)]);
+        assert_eq!(queue.len, 4);
+    }
+
+    #[cfg(feature = "buffered")]
+    fn buffered(tag: u8) -> BufferedFrame {
+        let frame = Frame::new_data(StandardId::new(0x100).unwrap(), &[tag]).unwrap();
+        BufferedFrame {
+            fifo: RxFifo::Fifo0,
+            info: RxFrameInfo {
+                len: 1,
+                frame_format: crate::frame::FrameFormat::Standard,
+                id: frame.id(),
+                rtr: false,
+                filter_match: None,
+                bit_rate_switching: false,
+                error_state_indicator: false,
+                time_stamp: tag.into(),
+            },
+            frame,
+        }
+    }
+
+    #[cfg(feature = "buffered")]
+    fn pop_tag<const N: usize>(ring: &mut Ring<N>) -> Option<u8> {
+        ring.pop().map(|f| f.frame.data()[0])
+    }
+
+    #[cfg(feature = "buffered")]
+    #[test]
+    fn ring_wraps_around() {
+        let mut ring = Ring::<3>::new();
+        assert_eq!(pop_tag(&mut ring), None);
+        for round in 0..4 {
+            ring.push(buffered(2 * round));
+            ring.push(buffered(2 * round + 1));
+            assert_eq!(pop_tag(&mut ring), Some(2 * round));
+            assert_eq!(pop_tag(&mut ring), Some(2 * round + 1));
+        }
+        assert_eq!((ring.head, ring.len), (8 % 3, 0));
+        assert_eq!(pop_tag(&mut ring), None);
+        assert_eq!(ring.dropped, 0);
+    }
+
+    #[cfg(feature = "buffered")]
+    #[test]
+    fn ring_drops_when_full() {
+        let mut ring = Ring::<3>::new();
+        ring.push(buffered(0));
+        assert_eq!(pop_tag(&mut ring), Some(0));
+        for tag in 1..6 {
+            ring.push(buffered(tag));
+        }
+        // The oldest frames are kept, the newest ones dropped
+        assert_eq!((ring.len, ring.dropped), (3, 2));
+        assert_eq!(pop_tag(&mut ring), Some(1));
+        ring.push(buffered(6));
+        assert_eq!(pop_tag(&mut ring), Some(2));
+        assert_eq!(pop_tag(&mut ring), Some(3));
+        assert_eq!(pop_tag(&mut ring), Some(6));
+        assert_eq!(pop_tag(&mut ring), None);
+
+        ring.dropped = u32::MAX;
+        for tag in 0..4 {
+            ring.push(buffered(tag));
+        }
+        assert_eq!(ring.dropped, 0);
+    }
+}
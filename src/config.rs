@@ -1,10 +1,15 @@
+pub use super::filter::RxFifo;
 pub use super::interrupt::{Interrupt, InterruptLine, Interrupts};
+#[cfg(feature = "fdcan_h7")]
+pub use super::message_ram::{DataFieldSize, MessageRamLayout};
 use core::num::{NonZeroU16, NonZeroU8};
 
 /// Configures the bit timings.
 ///
-/// You can use <http://www.bittiming.can-wiki.info/> to calculate the `btr` parameter. Enter
-/// parameters as follows:
+/// Use [`NominalBitTiming::calculate`] to find the timing for a bitrate and sample point.
+///
+/// Alternatively, you can use <http://www.bittiming.can-wiki.info/> to calculate the `btr`
+/// parameter. Enter parameters as follows:
 ///
 /// - *Clock Rate*: The input clock speed to the CAN peripheral (*not* the CPU clock speed).
 ///   This is the clock rate of the peripheral bus the CAN peripheral is attached to (eg. APB1).
@@ -18,17 +23,91 @@ pub struct NominalBitTiming {
     /// Value by which the oscillator frequency is divided for generating the bit time quanta. The bit
     /// time is built up from a multiple of this quanta. Valid values are 1 to 512.
     pub prescaler: NonZeroU16,
-    /// Valid values are 1 to 128.
+    /// Valid values are 1 to 255. The register also takes 256, which does not fit in a
+    /// `NonZeroU8`, so [`NominalBitTiming::calculate`] tops out at 255 as well.
     pub seg1: NonZeroU8,
-    /// Valid values are 1 to 255.
+    /// Valid values are 1 to 128.
     pub seg2: NonZeroU8,
     /// Valid values are 1 to 128.
     pub sync_jump_width: NonZeroU8,
 }
 impl NominalBitTiming {
+    const LIMITS: BitTimingLimits = BitTimingLimits {
+        max_prescaler: 512,
+        max_seg1: 255,
+        max_seg2: 128,
+        max_sjw: 128,
+    };
+
+    /// Calculates the timing closest to `bitrate` (in bit/s) and `sample_point` (in per mille
+    /// of the bit time) for a kernel clock of `kernel_clock` Hz.
+    ///
+    /// The timing with the smallest bitrate error is chosen, then the one with the smallest
+    /// sample point error. The sync jump width defaults to the length of the phase segment
+    /// after the sample point; an explicit `sync_jump_width` is limited to that length.
+    ///
+    /// Returns `None` if no timing within the register ranges gets close to `bitrate`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sample_point` is not between 1 and 999. In a const context this fails to
+    /// compile instead.
+    ///
+    /// ```ignore
+    /// const TIMING: BitTimingCalculation<NominalBitTiming> =
+    ///     match NominalBitTiming::calculate(80_000_000, 500_000, 875, None) {
+    ///         Some(t) => t,
+    ///         None => panic!("No bit timing for 500 kbit/s"),
+    ///     };
+    /// ```
+    pub const fn calculate(
+        kernel_clock: u32,
+        bitrate: u32,
+        sample_point: u16,
+        sync_jump_width: Option<NonZeroU8>,
+    ) -> Option<BitTimingCalculation<Self>> {
+        let t = match Self::LIMITS.calculate(
+            kernel_clock,
+            bitrate,
+            sample_point,
+            sync_jump_width,
+        ) {
+            Some(t) => t,
+            None => return None,
+        };
+        Some(BitTimingCalculation {
+            timing: Self {
+                prescaler: non_zero_u16(t.prescaler),
+                seg1: non_zero_u8(t.seg1),
+                seg2: non_zero_u8(t.seg2),
+                sync_jump_width: non_zero_u8(t.sync_jump_width),
+            },
+            bitrate: t.bitrate,
+            bitrate_error_ppm: t.bitrate_error_ppm,
+            sample_point: t.sample_point,
+        })
+    }
+
+    /// Returns the bitrate in bit/s for a kernel clock of `kernel_clock` Hz
+    #[inline]
+    pub const fn bitrate(&self, kernel_clock: u32) -> u32 {
+        bitrate(
+            kernel_clock,
+            self.prescaler.get(),
+            self.seg1.get() as u16,
+            self.seg2.get() as u16,
+        )
+    }
+
+    /// Returns the sample point in per mille of the bit time
+    #[inline]
+    pub const fn sample_point(&self) -> u16 {
+        sample_point(self.seg1.get() as u16, self.seg2.get() as u16)
+    }
+
     #[inline]
     pub(crate) fn nbrp(&self) -> u16 {
-        u16::from(self.prescaler) & 0x1FF
+        u16::from(self.prescaler)
     }
     #[inline]
     pub(crate) fn ntseg1(&self) -> u8 {
@@ -36,11 +115,11 @@ impl NominalBitTiming {
     }
     #[inline]
     pub(crate) fn ntseg2(&self) -> u8 {
-        u8::from(self.seg2) & 0x7F
+        u8::from(self.seg2)
     }
     #[inline]
     pub(crate) fn nsjw(&self) -> u8 {
-        u8::from(self.sync_jump_width) & 0x7F
+        u8::from(self.sync_jump_width)
     }
 }
 
@@ -66,37 +145,97 @@ pub struct DataBitTiming {
     pub transceiver_delay_compensation: bool,
     ///  The value by which the oscillator frequency is divided to generate the bit time quanta. The bit
     ///  time is built up from a multiple of this quanta. Valid values for the Baud Rate Prescaler are 1
-    ///  to 31.
+    ///  to 32.
     pub prescaler: NonZeroU8,
-    /// Valid values are 1 to 31.
+    /// Valid values are 1 to 32.
     pub seg1: NonZeroU8,
-    /// Valid values are 1 to 15.
+    /// Valid values are 1 to 16.
     pub seg2: NonZeroU8,
-    /// Must always be smaller than DTSEG2, valid values are 1 to 15.
+    /// Must always be smaller than DTSEG2, valid values are 1 to 16.
     pub sync_jump_width: NonZeroU8,
 }
 impl DataBitTiming {
-    // #[inline]
-    // fn tdc(&self) -> u8 {
-    //     let tsd = self.transceiver_delay_compensation as u8;
-    //     //TODO: stm32g4 does not export the TDC field
-    //     todo!()
-    // }
+    const LIMITS: BitTimingLimits = BitTimingLimits {
+        max_prescaler: 32,
+        max_seg1: 32,
+        max_seg2: 16,
+        max_sjw: 16,
+    };
+
+    /// Calculates the timing closest to `bitrate` (in bit/s) and `sample_point` (in per mille
+    /// of the bit time) for a kernel clock of `kernel_clock` Hz. See
+    /// [`NominalBitTiming::calculate`].
+    ///
+    /// Transceiver delay compensation is disabled in the returned timing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sample_point` is not between 1 and 999.
+    pub const fn calculate(
+        kernel_clock: u32,
+        bitrate: u32,
+        sample_point: u16,
+        sync_jump_width: Option<NonZeroU8>,
+    ) -> Option<BitTimingCalculation<Self>> {
+        let t = match Self::LIMITS.calculate(
+            kernel_clock,
+            bitrate,
+            sample_point,
+            sync_jump_width,
+        ) {
+            Some(t) => t,
+            None => return None,
+        };
+        Some(BitTimingCalculation {
+            timing: Self {
+                transceiver_delay_compensation: false,
+                prescaler: non_zero_u8(t.prescaler),
+                seg1: non_zero_u8(t.seg1),
+                seg2: non_zero_u8(t.seg2),
+                sync_jump_width: non_zero_u8(t.sync_jump_width),
+            },
+            bitrate: t.bitrate,
+            bitrate_error_ppm: t.bitrate_error_ppm,
+            sample_point: t.sample_point,
+        })
+    }
+
+    /// Returns the bitrate in bit/s for a kernel clock of `kernel_clock` Hz
+    #[inline]
+    pub const fn bitrate(&self, kernel_clock: u32) -> u32 {
+        bitrate(
+            kernel_clock,
+            self.prescaler.get() as u16,
+            self.seg1.get() as u16,
+            self.seg2.get() as u16,
+        )
+    }
+
+    /// Returns the sample point in per mille of the bit time
+    #[inline]
+    pub const fn sample_point(&self) -> u16 {
+        sample_point(self.seg1.get() as u16, self.seg2.get() as u16)
+    }
+
+    #[inline]
+    pub(crate) fn tdc(&self) -> bool {
+        self.transceiver_delay_compensation
+    }
     #[inline]
     pub(crate) fn dbrp(&self) -> u8 {
-        u8::from(self.prescaler) & 0x1F
+        u8::from(self.prescaler)
     }
     #[inline]
     pub(crate) fn dtseg1(&self) -> u8 {
-        u8::from(self.seg1) & 0x1F
+        u8::from(self.seg1)
     }
     #[inline]
     pub(crate) fn dtseg2(&self) -> u8 {
-        u8::from(self.seg2) & 0x0F
+        u8::from(self.seg2)
     }
     #[inline]
     pub(crate) fn dsjw(&self) -> u8 {
-        u8::from(self.sync_jump_width) & 0x0F
+        u8::from(self.sync_jump_width)
     }
 }
 
@@ -115,6 +254,205 @@ impl Default for DataBitTiming {
     }
 }
 
+/// Configures the transmitter delay compensation, used when
+/// [`DataBitTiming::transceiver_delay_compensation`] is enabled.
+///
+/// The secondary sample point used to check transmitted bits in the data phase is placed at
+/// the measured transmitter delay plus `offset`. Both values are in minimum time quanta, i.e.
+/// periods of the FDCAN kernel clock after the clock divider.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransmitterDelayCompensation {
+    /// Offset of the secondary sample point from the measured delay. Valid values are 0 to 127.
+    pub offset: u8,
+    /// Minimum position of the secondary sample point. Dominant edges that would place it
+    /// earlier are ignored when measuring the delay. Valid values are 0 to 127, 0 disables the
+    /// filter window.
+    pub filter_window: u8,
+}
+impl TransmitterDelayCompensation {
+    /// Places the secondary sample point at the sample point of the data bit timing `btr`,
+    /// without a filter window
+    pub const fn from_data_bit_timing(btr: &DataBitTiming) -> Self {
+        let offset = btr.prescaler.get() as u16 * (1 + btr.seg1.get() as u16);
+        Self {
+            offset: if offset > 127 { 127 } else { offset as u8 },
+            filter_window: 0,
+        }
+    }
+}
+
+/// A bit timing calculated by [`NominalBitTiming::calculate`] or
+/// [`DataBitTiming::calculate`]
+#[derive(Clone, Copy, Debug)]
+pub struct BitTimingCalculation<T> {
+    /// The calculated timing
+    pub timing: T,
+    /// Actual bitrate in bit/s
+    pub bitrate: u32,
+    /// Deviation of the actual bitrate from the requested bitrate in parts per million
+    pub bitrate_error_ppm: u32,
+    /// Actual sample point in per mille of the bit time
+    pub sample_point: u16,
+}
+
+/// Register ranges of a bit timing, in time quanta
+struct BitTimingLimits {
+    max_prescaler: u16,
+    max_seg1: u16,
+    max_seg2: u16,
+    max_sjw: u16,
+}
+
+struct RawBitTiming {
+    prescaler: u16,
+    seg1: u16,
+    seg2: u16,
+    sync_jump_width: u16,
+    bitrate: u32,
+    bitrate_error_ppm: u32,
+    sample_point: u16,
+}
+
+impl BitTimingLimits {
+    const fn calculate(
+        &self,
+        kernel_clock: u32,
+        bitrate: u32,
+        sample_point: u16,
+        sync_jump_width: Option<NonZeroU8>,
+    ) -> Option<RawBitTiming> {
+        assert!(
+            sample_point > 0 && sample_point < 1000,
+            "Sample point must be between 0 and 1000 per mille"
+        );
+        if bitrate == 0 {
+            return None;
+        }
+
+        // One time quantum for the sync segment
+        let max_tq = 1 + self.max_seg1 as u64 + self.max_seg2 as u64;
+        let mut best: Option<RawBitTiming> = None;
+        let mut best_sp_error = 0;
+
+        let mut prescaler = 1;
+        while prescaler <= self.max_prescaler {
+            let tq_rate = prescaler as u64 * bitrate as u64;
+            let floor = kernel_clock as u64 / tq_rate;
+
+            // The bitrate is closest for one of the two neighbouring numbers of time quanta
+            let mut tq = if floor > 0 { floor } else { 1 };
+            while tq <= floor + 1 {
+                if tq >= 3 && tq <= max_tq {
+                    let timing = self.split(
+                        kernel_clock,
+                        bitrate,
+                        sample_point,
+                        prescaler,
+                        tq as u16,
+                    );
+                    let sp_error = timing.sample_point.abs_diff(sample_point);
+
+                    let better = match &best {
+                        None => true,
+                        Some(b) => {
+                            timing.bitrate_error_ppm < b.bitrate_error_ppm
+                                || (timing.bitrate_error_ppm == b.bitrate_error_ppm
+                                    && sp_error < best_sp_error)
+                        }
+                    };
+                    if better {
+                        best = Some(timing);
+                        best_sp_error = sp_error;
+                    }
+                }
+                tq += 1;
+            }
+            prescaler += 1;
+        }
+
+        match best {
+            Some(mut b) => {
+                let max_sjw = min(min(b.seg1, b.seg2), self.max_sjw);
+                b.sync_jump_width = match sync_jump_width {
+                    Some(sjw) => min(sjw.get() as u16, max_sjw),
+                    None => max_sjw,
+                };
+                Some(b)
+            }
+            None => None,
+        }
+    }
+
+    /// Splits a bit time of `tq` time quanta into the segments before and after the sample
+    /// point
+    const fn split(
+        &self,
+        kernel_clock: u32,
+        bitrate: u32,
+        sample_point: u16,
+        prescaler: u16,
+        tq: u16,
+    ) -> RawBitTiming {
+        // Time quanta before the sample point, including the sync segment
+        let before = ((tq as u32 * sample_point as u32 + 500) / 1000) as u16;
+        let mut seg1 = if before > 1 { before - 1 } else { 1 };
+        if seg1 > tq - 2 {
+            seg1 = tq - 2;
+        }
+        if seg1 > self.max_seg1 {
+            seg1 = self.max_seg1;
+        }
+        if tq - 1 - seg1 > self.max_seg2 {
+            seg1 = tq - 1 - self.max_seg2;
+        }
+        let seg2 = tq - 1 - seg1;
+
+        let actual = self::bitrate(kernel_clock, prescaler, seg1, seg2);
+        RawBitTiming {
+            prescaler,
+            seg1,
+            seg2,
+            sync_jump_width: 1,
+            bitrate: actual,
+            bitrate_error_ppm: (actual.abs_diff(bitrate) as u64 * 1_000_000
+                / bitrate as u64) as u32,
+            sample_point: self::sample_point(seg1, seg2),
+        }
+    }
+}
+
+const fn min(a: u16, b: u16) -> u16 {
+    if a < b {
+        a
+    } else {
+        b
+    }
+}
+
+const fn non_zero_u8(value: u16) -> NonZeroU8 {
+    match NonZeroU8::new(value as u8) {
+        Some(v) => v,
+        None => panic!("Bit timing value out of range"),
+    }
+}
+
+const fn non_zero_u16(value: u16) -> NonZeroU16 {
+    match NonZeroU16::new(value) {
+        Some(v) => v,
+        None => panic!("Bit timing value out of range"),
+    }
+}
+
+const fn bitrate(kernel_clock: u32, prescaler: u16, seg1: u16, seg2: u16) -> u32 {
+    let tq = 1 + seg1 as u32 + seg2 as u32;
+    kernel_clock / (prescaler as u32 * tq)
+}
+
+const fn sample_point(seg1: u16, seg2: u16) -> u16 {
+    let tq = 1 + seg1 as u32 + seg2 as u32;
+    ((1 + seg1 as u32) * 1000 / tq) as u16
+}
+
 /// Configures which modes to use
 /// Individual headers can contain a desire to be send via FdCan
 /// or use Bit rate switching. But if this general setting does not allow
@@ -129,6 +467,24 @@ pub enum FrameTransmissionConfig {
     AllowFdCanAndBRS,
 }
 
+/// How the Tx buffers that are not dedicated Tx buffers are used
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TxBufferMode {
+    /// Frames are transmitted in the order they were put in the Tx FIFO
+    Fifo,
+    /// Frames are transmitted by priority (identifier), as in a priority queue
+    Queue,
+}
+
+/// What a full Rx FIFO does with new frames
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FifoMode {
+    /// New frames are dropped, keeping the oldest frames
+    Blocking,
+    /// New frames overwrite the oldest frame, keeping the newest frames
+    Overwrite,
+}
+
 ///
 #[derive(Clone, Copy, Debug)]
 pub enum ClockDivider {
@@ -215,6 +571,45 @@ pub enum TimestampSource {
     FromTIM3,
 }
 
+/// Configures the timeout counter. The period is given in CAN bit times, multiplied by the
+/// [`TimestampPrescaler`] of the timestamp counter.
+///
+/// When the counter reaches zero, [`Interrupt::TimeoutOccurred`] is flagged and the counter
+/// stops until it is restarted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeoutConfig {
+    /// The timeout counter is disabled
+    Disabled,
+    /// Counts down continuously, restarted with `reset_timeout`
+    Continuous(u16),
+    /// Counts down while the Tx Event FIFO is not empty, restarted when it becomes empty
+    TxEventFifo(u16),
+    /// Counts down while Rx FIFO 0 is not empty, restarted when it becomes empty
+    RxFifo0(u16),
+    /// Counts down while Rx FIFO 1 is not empty, restarted when it becomes empty
+    RxFifo1(u16),
+}
+
+/// What to do when the FdCan enters the Bus_Off state, see [`FdCan::poll_recovery`]
+///
+/// [`FdCan::poll_recovery`]: crate::FdCan::poll_recovery
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusOffRecovery {
+    /// Start the recovery as soon as Bus_Off is detected
+    Automatic,
+    /// Stay in Bus_Off until the recovery is started with `start_recovery`
+    Manual,
+    /// Wait `delay` polls before the first recovery attempt, doubling the delay for every
+    /// following attempt. After `max_attempts` attempts, the FdCan stays in Bus_Off. The
+    /// attempts are counted per Bus_Off event.
+    BackOff {
+        /// Number of polls before the first attempt
+        delay: u16,
+        /// Maximum number of recovery attempts
+        max_attempts: u8,
+    },
+}
+
 /// How to handle frames in the global filter
 #[derive(Clone, Copy, Debug)]
 pub enum NonMatchingFilter {
@@ -240,6 +635,10 @@ pub struct GlobalFilter {
 
     /// How to handle remote extended frames
     pub reject_remote_extended_frames: bool,
+
+    /// AND mask applied to extended IDs before filtering. Bits cleared in the mask are ignored
+    /// by the extended filters, e.g. the source address byte of J1939 frames.
+    pub extended_id_mask: u32,
 }
 impl GlobalFilter {
     /// Reject all non-matching and remote frames
@@ -249,6 +648,7 @@ impl GlobalFilter {
             handle_extended_frames: NonMatchingFilter::Reject,
             reject_remote_standard_frames: true,
             reject_remote_extended_frames: true,
+            extended_id_mask: 0x1FFF_FFFF,
         }
     }
 
@@ -278,6 +678,12 @@ impl GlobalFilter {
         self.reject_remote_extended_frames = filter;
         self
     }
+    /// AND mask applied to extended IDs before filtering. Defaults to `0x1FFF_FFFF`, which
+    /// passes the ID unchanged.
+    pub const fn set_extended_id_mask(mut self, mask: u32) -> Self {
+        self.extended_id_mask = mask;
+        self
+    }
 }
 impl Default for GlobalFilter {
     #[inline]
@@ -287,6 +693,7 @@ impl Default for GlobalFilter {
             handle_extended_frames: NonMatchingFilter::IntoRxFifo0,
             reject_remote_standard_frames: false,
             reject_remote_extended_frames: false,
+            extended_id_mask: 0x1FFF_FFFF,
         }
     }
 }
@@ -336,6 +743,25 @@ pub struct FdCanConfig {
     pub timestamp_source: TimestampSource,
     /// Configures the Global Filter
     pub global_filter: GlobalFilter,
+    /// Configures the timeout counter
+    pub timeout: TimeoutConfig,
+    /// Start value of the Message RAM watchdog, see [`FdCanConfig::set_ram_watchdog`]
+    pub ram_watchdog: u8,
+    /// Bus_Off recovery policy
+    pub bus_off_recovery: BusOffRecovery,
+    /// Tx FIFO or Tx queue mode
+    pub tx_buffer_mode: TxBufferMode,
+    /// Operation mode of Rx FIFO 0 and 1
+    pub rx_fifo_mode: [FifoMode; 2],
+    /// Watermark of Rx FIFO 0 and 1, see [`FdCanConfig::set_rx_fifo_watermark`]
+    #[cfg(feature = "fdcan_h7")]
+    pub rx_fifo_watermark: [u8; 2],
+    /// Transmitter delay compensation. When `None`, it is derived from the data bit timing with
+    /// [`TransmitterDelayCompensation::from_data_bit_timing`].
+    pub transmitter_delay_compensation: Option<TransmitterDelayCompensation>,
+    /// Layout of the Message RAM region used by this instance
+    #[cfg(feature = "fdcan_h7")]
+    pub message_ram_layout: MessageRamLayout,
 }
 
 impl FdCanConfig {
@@ -353,6 +779,17 @@ impl FdCanConfig {
         self
     }
 
+    /// Configures the transmitter delay compensation offset and filter window. By default these
+    /// are derived from the data bit timing.
+    #[inline]
+    pub const fn set_transmitter_delay_compensation(
+        mut self,
+        tdc: TransmitterDelayCompensation,
+    ) -> Self {
+        self.transmitter_delay_compensation = Some(tdc);
+        self
+    }
+
     /// Enables or disables automatic retransmission of messages
     ///
     /// If this is enabled, the CAN peripheral will automatically try to retransmit each frame
@@ -438,12 +875,75 @@ impl FdCanConfig {
         self
     }
 
+    /// Configures the timeout counter
+    #[inline]
+    pub const fn set_timeout(mut self, timeout: TimeoutConfig) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets the start value of the Message RAM watchdog, in periods of the peripheral bus
+    /// clock. 0 disables the watchdog.
+    ///
+    /// The watchdog counts down while the Message RAM does not complete an access. When it
+    /// reaches zero, [`Interrupt::WatchdogInt`] is flagged.
+    #[inline]
+    pub const fn set_ram_watchdog(mut self, start: u8) -> Self {
+        self.ram_watchdog = start;
+        self
+    }
+
+    /// Sets the Bus_Off recovery policy. Defaults to [`BusOffRecovery::Manual`]
+    #[inline]
+    pub const fn set_bus_off_recovery(mut self, policy: BusOffRecovery) -> Self {
+        self.bus_off_recovery = policy;
+        self
+    }
+
     /// Sets the global filter settings
     #[inline]
     pub const fn set_global_filter(mut self, filter: GlobalFilter) -> Self {
         self.global_filter = filter;
         self
     }
+
+    /// Sets the Tx buffer mode. Defaults to [`TxBufferMode::Queue`]
+    #[inline]
+    pub const fn set_tx_buffer_mode(mut self, txbm: TxBufferMode) -> Self {
+        self.tx_buffer_mode = txbm;
+        self
+    }
+
+    /// Sets the operation mode of an Rx FIFO. Defaults to [`FifoMode::Blocking`]
+    #[inline]
+    pub const fn set_rx_fifo_mode(mut self, fifo: RxFifo, mode: FifoMode) -> Self {
+        self.rx_fifo_mode[fifo as usize] = mode;
+        self
+    }
+
+    /// Sets the watermark of an Rx FIFO. [`Interrupt::RxFifo0Watermark`] or
+    /// [`Interrupt::RxFifo1Watermark`] is flagged when the fill level reaches the watermark.
+    /// 0 disables the watermark interrupt.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `watermark` is larger than 64.
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    pub const fn set_rx_fifo_watermark(mut self, fifo: RxFifo, watermark: u8) -> Self {
+        assert!(watermark <= 64, "Rx FIFO watermark is larger than 64");
+        self.rx_fifo_watermark[fifo as usize] = watermark;
+        self
+    }
+
+    /// Sets the Message RAM layout. The layout is checked, which makes this a compile time
+    /// error for invalid layouts when the config is built in a `const`.
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    pub const fn set_message_ram_layout(mut self, layout: MessageRamLayout) -> Self {
+        self.message_ram_layout = layout.check();
+        self
+    }
 }
 
 impl Default for FdCanConfig {
@@ -461,7 +961,133 @@ impl Default for FdCanConfig {
             protocol_exception_handling: true,
             clock_divider: ClockDivider::_1,
             timestamp_source: TimestampSource::None,
+            timeout: TimeoutConfig::Disabled,
+            ram_watchdog: 0,
+            bus_off_recovery: BusOffRecovery::Manual,
             global_filter: GlobalFilter::default(),
+            tx_buffer_mode: TxBufferMode::Queue,
+            rx_fifo_mode: [FifoMode::Blocking; 2],
+            #[cfg(feature = "fdcan_h7")]
+            rx_fifo_watermark: [0; 2],
+            transmitter_delay_compensation: None,
+            #[cfg(feature = "fdcan_h7")]
+            message_ram_layout: MessageRamLayout::new(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KERNEL_CLOCK: u32 = 80_000_000;
+
+    #[test]
+    fn exact_bitrates() {
+        let t = NominalBitTiming::calculate(KERNEL_CLOCK, 500_000, 875, None).unwrap();
+        assert_eq!(
+            (t.bitrate, t.bitrate_error_ppm, t.sample_point),
+            (500_000, 0, 875)
+        );
+        // The smallest prescaler gives the finest sample point
+        let timing = t.timing;
+        assert_eq!(
+            (
+                timing.nbrp(),
+                timing.ntseg1(),
+                timing.ntseg2(),
+                timing.nsjw()
+            ),
+            (1, 139, 20, 20)
+        );
+        assert_eq!(timing.bitrate(KERNEL_CLOCK), 500_000);
+        assert_eq!(timing.sample_point(), 875);
+
+        let t = DataBitTiming::calculate(KERNEL_CLOCK, 2_000_000, 750, None).unwrap();
+        assert_eq!(
+            (t.bitrate, t.bitrate_error_ppm, t.sample_point),
+            (2_000_000, 0, 750)
+        );
+        let timing = t.timing;
+        assert_eq!(
+            (
+                timing.dbrp(),
+                timing.dtseg1(),
+                timing.dtseg2(),
+                timing.dsjw()
+            ),
+            (1, 29, 10, 10)
+        );
+
+        // An explicit sync jump width is limited to the phase segment after the sample point
+        let sjw = NonZeroU8::new(200);
+        let t = NominalBitTiming::calculate(KERNEL_CLOCK, 500_000, 875, sjw).unwrap();
+        assert_eq!(t.timing.nsjw(), 20);
+        let sjw = NonZeroU8::new(4);
+        let t = NominalBitTiming::calculate(KERNEL_CLOCK, 500_000, 875, sjw).unwrap();
+        assert_eq!(t.timing.nsjw(), 4);
+    }
+
+    #[test]
+    fn closest_bitrate() {
+        // 80 MHz is not a multiple of 300 kbit/s
+        let t = NominalBitTiming::calculate(KERNEL_CLOCK, 300_000, 800, None).unwrap();
+        assert!(t.bitrate_error_ppm > 0);
+        assert_eq!(t.timing.bitrate(KERNEL_CLOCK), t.bitrate);
+        assert_eq!(
+            u64::from(t.bitrate.abs_diff(300_000)) * 1_000_000 / 300_000,
+            u64::from(t.bitrate_error_ppm)
+        );
+
+        // No other number of time quanta gets closer
+        let limits = NominalBitTiming::LIMITS;
+        for prescaler in 1..=limits.max_prescaler {
+            for tq in 3..=1 + limits.max_seg1 + limits.max_seg2 {
+                let other = limits.split(KERNEL_CLOCK, 300_000, 800, prescaler, tq);
+                assert!(other.bitrate_error_ppm >= t.bitrate_error_ppm);
+            }
+        }
+    }
+
+    #[test]
+    fn unreachable_bitrates() {
+        assert!(NominalBitTiming::calculate(KERNEL_CLOCK, 0, 875, None).is_none());
+        // Slower than the largest prescaler and bit time allow
+        assert!(NominalBitTiming::calculate(KERNEL_CLOCK, 100, 875, None).is_none());
+        assert!(DataBitTiming::calculate(KERNEL_CLOCK, 50_000, 750, None).is_none());
+        // Faster than the kernel clock
+        assert!(
+            NominalBitTiming::calculate(KERNEL_CLOCK, 100_000_000, 875, None).is_none()
+        );
+
+        // The shortest bit time of 3 time quanta is the closest to a third of the kernel clock
+        let t = DataBitTiming::calculate(KERNEL_CLOCK, 40_000_000, 750, None).unwrap();
+        assert_eq!((t.bitrate, t.bitrate_error_ppm), (26_666_666, 333_333));
+        assert_eq!((t.timing.dtseg1(), t.timing.dtseg2()), (1, 1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Sample point must be between 0 and 1000 per mille")]
+    fn sample_point_out_of_range() {
+        let _ = DataBitTiming::calculate(KERNEL_CLOCK, 2_000_000, 1000, None);
+    }
+
+    #[test]
+    fn split_limits() {
+        let split = |limits: &BitTimingLimits, sample_point, tq| {
+            let t = limits.split(KERNEL_CLOCK, 1_000_000, sample_point, 1, tq);
+            (t.seg1, t.seg2)
+        };
+        let nominal = NominalBitTiming::LIMITS;
+        assert_eq!(split(&nominal, 875, 160), (139, 20));
+        // At least one time quantum on either side of the sample point
+        assert_eq!(split(&nominal, 999, 10), (8, 1));
+        assert_eq!(split(&nominal, 1, 10), (1, 8));
+        // Limited by the largest segments
+        assert_eq!(split(&nominal, 999, 384), (255, 128));
+        assert_eq!(split(&nominal, 500, 384), (255, 128));
+        let data = DataBitTiming::LIMITS;
+        assert_eq!(split(&data, 500, 49), (32, 16));
+        assert_eq!(split(&data, 900, 40), (32, 7));
+    }
+}
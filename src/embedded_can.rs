@@ -0,0 +1,219 @@
+//! `embedded_can` trait impls.
+//!
+//! [`FdCan`] implements the non-blocking `Can` trait (`embedded_can::Can` in 0.3,
+//! `embedded_can::nb::Can` in 0.4). The split [`Tx`] and [`Rx`] halves cannot
+//! implement it on their own, as it combines transmitting and receiving; they offer the same
+//! operations through [`Tx::transmit_frame`] and [`Rx::receive_frame`] instead.
+
+use crate::frame::Frame;
+use crate::id::{ExtendedId, Id, StandardId};
+use crate::{FdCan, Instance, Receive, Transmit};
+#[allow(unused_imports)] // for intra-doc links only
+use crate::{Rx, Tx};
+
+use core::convert::Infallible;
+
+/// Receives a frame from FIFO_0, or from FIFO_1 when FIFO_0 is empty.
+fn receive_any<I, M>(can: &mut FdCan<I, M>) -> nb::Result<Frame, Infallible>
+where
+    I: Instance,
+    M: Receive,
+{
+    let mut buffer = [0_u8; 64];
+    let info = match can.receive0(&mut buffer) {
+        Err(nb::Error::WouldBlock) => can.receive1(&mut buffer)?,
+        result => result?,
+    };
+    Ok(Frame::from_rx(info.unwrap(), &buffer))
+}
+
+/// Transmits a frame, returning a lower priority frame that was replaced.
+fn transmit_any<I, M>(
+    can: &mut FdCan<I, M>,
+    frame: &Frame,
+) -> nb::Result<Option<Frame>, Infallible>
+where
+    I: Instance,
+    M: Transmit,
+{
+    can.transmit_preserve(frame.tx_header(None), frame.data(), &mut |_, h, d| {
+        Frame::from_tx(h, d)
+    })
+}
+
+macro_rules! impl_id_conversions {
+    ($ecan:ident) => {
+        impl From<StandardId> for $ecan::StandardId {
+            #[inline]
+            fn from(id: StandardId) -> Self {
+                // Safety: both types hold an 11-bit identifier
+                unsafe { $ecan::StandardId::new_unchecked(id.as_raw()) }
+            }
+        }
+        impl From<$ecan::StandardId> for StandardId {
+            #[inline]
+            fn from(id: $ecan::StandardId) -> Self {
+                // Safety: both types hold an 11-bit identifier
+                unsafe { StandardId::new_unchecked(id.as_raw()) }
+            }
+        }
+        impl From<ExtendedId> for $ecan::ExtendedId {
+            #[inline]
+            fn from(id: ExtendedId) -> Self {
+                // Safety: both types hold a 29-bit identifier
+                unsafe { $ecan::ExtendedId::new_unchecked(id.as_raw()) }
+            }
+        }
+        impl From<$ecan::ExtendedId> for ExtendedId {
+            #[inline]
+            fn from(id: $ecan::ExtendedId) -> Self {
+                // Safety: both types hold a 29-bit identifier
+                unsafe { ExtendedId::new_unchecked(id.as_raw()) }
+            }
+        }
+        impl From<Id> for $ecan::Id {
+            #[inline]
+            fn from(id: Id) -> Self {
+                match id {
+                    Id::Standard(id) => $ecan::Id::Standard(id.into()),
+                    Id::Extended(id) => $ecan::Id::Extended(id.into()),
+                }
+            }
+        }
+        impl From<$ecan::Id> for Id {
+            #[inline]
+            fn from(id: $ecan::Id) -> Self {
+                match id {
+                    $ecan::Id::Standard(id) => Id::Standard(id.into()),
+                    $ecan::Id::Extended(id) => Id::Extended(id.into()),
+                }
+            }
+        }
+    };
+}
+
+#[cfg(feature = "embedded-can-03")]
+mod v03 {
+    use super::*;
+    use embedded_can_03 as ecan;
+
+    impl_id_conversions!(ecan);
+
+    impl ecan::Frame for Frame {
+        fn new(id: impl Into<ecan::Id>, data: &[u8]) -> Result<Self, ()> {
+            Frame::new_data(Id::from(id.into()), data).ok_or(())
+        }
+
+        fn new_remote(id: impl Into<ecan::Id>, dlc: usize) -> Result<Self, ()> {
+            Frame::new_remote(Id::from(id.into()), dlc).ok_or(())
+        }
+
+        #[inline]
+        fn is_extended(&self) -> bool {
+            matches!(self.id(), Id::Extended(_))
+        }
+
+        #[inline]
+        fn is_remote_frame(&self) -> bool {
+            Frame::is_remote_frame(self)
+        }
+
+        #[inline]
+        fn id(&self) -> ecan::Id {
+            Frame::id(self).into()
+        }
+
+        #[inline]
+        fn dlc(&self) -> usize {
+            self.len().into()
+        }
+
+        #[inline]
+        fn data(&self) -> &[u8] {
+            Frame::data(self)
+        }
+    }
+
+    impl<I, M> ecan::Can for FdCan<I, M>
+    where
+        I: Instance,
+        M: Transmit + Receive,
+    {
+        type Frame = Frame;
+        type Error = Infallible;
+
+        fn try_transmit(
+            &mut self,
+            frame: &Self::Frame,
+        ) -> nb::Result<Option<Self::Frame>, Self::Error> {
+            transmit_any(self, frame)
+        }
+
+        fn try_receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
+            receive_any(self)
+        }
+    }
+}
+
+#[cfg(feature = "embedded-can-04")]
+mod v04 {
+    use super::*;
+    use embedded_can_04 as ecan;
+
+    impl_id_conversions!(ecan);
+
+    impl ecan::Frame for Frame {
+        fn new(id: impl Into<ecan::Id>, data: &[u8]) -> Option<Self> {
+            Frame::new_data(Id::from(id.into()), data)
+        }
+
+        fn new_remote(id: impl Into<ecan::Id>, dlc: usize) -> Option<Self> {
+            Frame::new_remote(Id::from(id.into()), dlc)
+        }
+
+        #[inline]
+        fn is_extended(&self) -> bool {
+            matches!(self.id(), Id::Extended(_))
+        }
+
+        #[inline]
+        fn is_remote_frame(&self) -> bool {
+            Frame::is_remote_frame(self)
+        }
+
+        #[inline]
+        fn id(&self) -> ecan::Id {
+            Frame::id(self).into()
+        }
+
+        #[inline]
+        fn dlc(&self) -> usize {
+            self.len().into()
+        }
+
+        #[inline]
+        fn data(&self) -> &[u8] {
+            Frame::data(self)
+        }
+    }
+
+    impl<I, M> ecan::nb::Can for FdCan<I, M>
+    where
+        I: Instance,
+        M: Transmit + Receive,
+    {
+        type Frame = Frame;
+        type Error = Infallible;
+
+        fn transmit(
+            &mut self,
+            frame: &Self::Frame,
+        ) -> nb::Result<Option<Self::Frame>, Self::Error> {
+            transmit_any(self, frame)
+        }
+
+        fn receive(&mut self) -> nb::Result<Self::Frame, Self::Error> {
+            receive_any(self)
+        }
+    }
+}
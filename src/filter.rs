@@ -2,6 +2,12 @@ use crate::id::{ExtendedId, StandardId};
 
 pub use message_ram::{EXTENDED_FILTER_MAX, STANDARD_FILTER_MAX};
 
+mod set;
+pub use set::{
+    ExtendedFilterSet, FilterSet, FilterSetError, FilterSetId, RxFifo,
+    StandardFilterSet,
+};
+
 /// A Standard Filter
 pub type StandardFilter = Filter<StandardId, u16>;
 /// An Extended Filter
@@ -59,6 +65,15 @@ impl StandardFilter {
             action: Action::Disable,
         }
     }
+
+    /// Store messages with `id` in the dedicated Rx buffer `buffer`
+    #[cfg(feature = "fdcan_h7")]
+    pub fn store_in_rx_buffer(id: StandardId, buffer: u8) -> StandardFilter {
+        StandardFilter {
+            filter: FilterType::DedicatedSingle(id),
+            action: Action::StoreInRxBuffer(buffer),
+        }
+    }
 }
 
 impl ExtendedFilter {
@@ -102,6 +117,15 @@ impl ExtendedFilter {
             action: Action::Disable,
         }
     }
+
+    /// Store messages with `id` in the dedicated Rx buffer `buffer`
+    #[cfg(feature = "fdcan_h7")]
+    pub fn store_in_rx_buffer(id: ExtendedId, buffer: u8) -> ExtendedFilter {
+        ExtendedFilter {
+            filter: FilterType::DedicatedSingle(id),
+            action: Action::StoreInRxBuffer(buffer),
+        }
+    }
 }
 
 /// Filter Type
@@ -152,19 +176,25 @@ where
 #[derive(Clone, Copy, Debug)]
 pub enum Action {
     /// No Action
-    Disable = 0b000,
+    Disable,
     /// Store an matching message in FIFO 0
-    StoreInFifo0 = 0b001,
+    StoreInFifo0,
     /// Store an matching message in FIFO 1
-    StoreInFifo1 = 0b010,
+    StoreInFifo1,
     /// Reject an matching message
-    Reject = 0b011,
+    Reject,
     /// Flag a matching message (But not store?!?)
-    FlagHighPrio = 0b100,
+    FlagHighPrio,
     /// Flag a matching message as a High Priority message and store it in FIFO 0
-    FlagHighPrioAndStoreInFifo0 = 0b101,
+    FlagHighPrioAndStoreInFifo0,
     /// Flag a matching message as a High Priority message and store it in FIFO 1
-    FlagHighPrioAndStoreInFifo1 = 0b110,
+    FlagHighPrioAndStoreInFifo1,
+    /// Store a matching message in the dedicated Rx buffer with the given index (0 to 63)
+    ///
+    /// Only a single ID is matched, the filter type is ignored. Use it with
+    /// [`FilterType::DedicatedSingle`].
+    #[cfg(feature = "fdcan_h7")]
+    StoreInRxBuffer(u8),
 }
 impl From<Action> for crate::message_ram::enums::FilterElementConfig {
     fn from(a: Action) -> Self {
@@ -176,6 +206,8 @@ impl From<Action> for crate::message_ram::enums::FilterElementConfig {
             Action::FlagHighPrio => Self::SetPriority,
             Action::FlagHighPrioAndStoreInFifo0 => Self::SetPriorityAndStoreInFifo0,
             Action::FlagHighPrioAndStoreInFifo1 => Self::SetPriorityAndStoreInFifo1,
+            #[cfg(feature = "fdcan_h7")]
+            Action::StoreInRxBuffer(_) => Self::StoreInRxBuffer,
         }
     }
 }
@@ -193,137 +225,73 @@ where
     pub action: Action,
 }
 
-/// Standard Filter Slot
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum StandardFilterSlot {
-    /// 0
-    _0 = 0,
-    /// 1
-    _1 = 1,
-    /// 2
-    _2 = 2,
-    /// 3
-    _3 = 3,
-    /// 4
-    _4 = 4,
-    /// 5
-    _5 = 5,
-    /// 6
-    _6 = 6,
-    /// 7
-    _7 = 7,
-    /// 8
-    _8 = 8,
-    /// 9
-    _9 = 9,
-    /// 10
-    _10 = 10,
-    /// 11
-    _11 = 11,
-    /// 12
-    _12 = 12,
-    /// 13
-    _13 = 13,
-    /// 14
-    _14 = 14,
-    /// 15
-    _15 = 15,
-    /// 16
-    _16 = 16,
-    /// 17
-    _17 = 17,
-    /// 18
-    _18 = 18,
-    /// 19
-    _19 = 19,
-    /// 20
-    _20 = 20,
-    /// 21
-    _21 = 21,
-    /// 22
-    _22 = 22,
-    /// 23
-    _23 = 23,
-    /// 24
-    _24 = 24,
-    /// 25
-    _25 = 25,
-    /// 26
-    _26 = 26,
-    /// 27
-    _27 = 27,
-}
-impl From<u8> for StandardFilterSlot {
-    fn from(u: u8) -> Self {
-        match u {
-            0 => StandardFilterSlot::_0,
-            1 => StandardFilterSlot::_1,
-            2 => StandardFilterSlot::_2,
-            3 => StandardFilterSlot::_3,
-            4 => StandardFilterSlot::_4,
-            5 => StandardFilterSlot::_5,
-            6 => StandardFilterSlot::_6,
-            7 => StandardFilterSlot::_7,
-            8 => StandardFilterSlot::_8,
-            9 => StandardFilterSlot::_9,
-            10 => StandardFilterSlot::_10,
-            11 => StandardFilterSlot::_11,
-            12 => StandardFilterSlot::_12,
-            13 => StandardFilterSlot::_13,
-            14 => StandardFilterSlot::_14,
-            15 => StandardFilterSlot::_15,
-            16 => StandardFilterSlot::_16,
-            17 => StandardFilterSlot::_17,
-            18 => StandardFilterSlot::_18,
-            19 => StandardFilterSlot::_19,
-            20 => StandardFilterSlot::_20,
-            21 => StandardFilterSlot::_21,
-            22 => StandardFilterSlot::_22,
-            23 => StandardFilterSlot::_23,
-            24 => StandardFilterSlot::_24,
-            25 => StandardFilterSlot::_25,
-            26 => StandardFilterSlot::_26,
-            27 => StandardFilterSlot::_27,
-            _ => panic!("Standard Filter Slot Too High!"),
+macro_rules! declare_filter_slots {
+    ($name:ident, $doc:literal, $panic:literal, [$($index:literal),*]) => {
+        paste::paste! {
+            #[doc = $doc]
+            #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+            pub enum $name {
+                $(
+                    #[doc = "" $index]
+                    [<_ $index>] = $index,
+                )*
+            }
+            impl From<u8> for $name {
+                fn from(u: u8) -> Self {
+                    match u {
+                        $($index => $name::[<_ $index>],)*
+                        _ => panic!($panic),
+                    }
+                }
+            }
         }
-    }
+    };
 }
 
-/// Extended Filter Slot
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum ExtendedFilterSlot {
-    /// 0
-    _0 = 0,
-    /// 1
-    _1 = 1,
-    /// 2
-    _2 = 2,
-    /// 3
-    _3 = 3,
-    /// 4
-    _4 = 4,
-    /// 5
-    _5 = 5,
-    /// 6
-    _6 = 6,
-    /// 7
-    _7 = 7,
-}
-impl From<u8> for ExtendedFilterSlot {
-    fn from(u: u8) -> Self {
-        match u {
-            0 => ExtendedFilterSlot::_0,
-            1 => ExtendedFilterSlot::_1,
-            2 => ExtendedFilterSlot::_2,
-            3 => ExtendedFilterSlot::_3,
-            4 => ExtendedFilterSlot::_4,
-            5 => ExtendedFilterSlot::_5,
-            6 => ExtendedFilterSlot::_6,
-            7 => ExtendedFilterSlot::_7,
-            _ => panic!("Extended Filter Slot Too High!"), // Should be unreachable
-        }
-    }
-}
+#[cfg(feature = "fdcan_g0_g4_l5")]
+declare_filter_slots!(
+    StandardFilterSlot,
+    "Standard Filter Slot",
+    "Standard Filter Slot Too High!",
+    [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21,
+        22, 23, 24, 25, 26, 27
+    ]
+);
+#[cfg(feature = "fdcan_g0_g4_l5")]
+declare_filter_slots!(
+    ExtendedFilterSlot,
+    "Extended Filter Slot",
+    "Extended Filter Slot Too High!",
+    [0, 1, 2, 3, 4, 5, 6, 7]
+);
+#[cfg(feature = "fdcan_h7")]
+declare_filter_slots!(
+    StandardFilterSlot,
+    "Standard Filter Slot",
+    "Standard Filter Slot Too High!",
+    [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21,
+        22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41,
+        42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61,
+        62, 63, 64, 65, 66, 67, 68, 69, 70, 71, 72, 73, 74, 75, 76, 77, 78, 79, 80, 81,
+        82, 83, 84, 85, 86, 87, 88, 89, 90, 91, 92, 93, 94, 95, 96, 97, 98, 99, 100,
+        101, 102, 103, 104, 105, 106, 107, 108, 109, 110, 111, 112, 113, 114, 115, 116,
+        117, 118, 119, 120, 121, 122, 123, 124, 125, 126, 127
+    ]
+);
+#[cfg(feature = "fdcan_h7")]
+declare_filter_slots!(
+    ExtendedFilterSlot,
+    "Extended Filter Slot",
+    "Extended Filter Slot Too High!",
+    [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21,
+        22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38, 39, 40, 41,
+        42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55, 56, 57, 58, 59, 60, 61,
+        62, 63
+    ]
+);
 
 /// Enum over both Standard and Extended Filter ID's
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -340,22 +308,86 @@ where
     UNIT: Copy + Clone + core::fmt::Debug,
 {
     fn activate(&mut self, f: Filter<ID, UNIT>);
-    // fn read(&self) -> Filter<ID, UNIT>;
+    fn read_filter(&self) -> Filter<ID, UNIT>;
 }
 
 use crate::message_ram;
+use crate::message_ram::enums::FilterElementConfig;
+use crate::message_ram::enums::FilterType as PacFilterType;
+
+/// Decodes the filter element config. `id2` holds the Rx buffer index for
+/// [`Action::StoreInRxBuffer`].
+#[cfg_attr(not(feature = "fdcan_h7"), allow(unused_variables))]
+fn read_action(fec: FilterElementConfig, id2: u32) -> Action {
+    match fec {
+        FilterElementConfig::DisableFilterElement => Action::Disable,
+        FilterElementConfig::StoreInFifo0 => Action::StoreInFifo0,
+        FilterElementConfig::StoreInFifo1 => Action::StoreInFifo1,
+        FilterElementConfig::Reject => Action::Reject,
+        FilterElementConfig::SetPriority => Action::FlagHighPrio,
+        FilterElementConfig::SetPriorityAndStoreInFifo0 => {
+            Action::FlagHighPrioAndStoreInFifo0
+        }
+        FilterElementConfig::SetPriorityAndStoreInFifo1 => {
+            Action::FlagHighPrioAndStoreInFifo1
+        }
+        #[cfg(feature = "fdcan_h7")]
+        FilterElementConfig::StoreInRxBuffer => {
+            Action::StoreInRxBuffer((id2 & 0x3f) as u8)
+        }
+    }
+}
+
+/// Decodes the filter type and ids, inverting `activate`
+fn read_filter_type<ID, UNIT>(
+    ft: PacFilterType,
+    action: Action,
+    (id1, id2): (UNIT, UNIT),
+    to_id: impl Fn(UNIT) -> ID,
+) -> FilterType<ID, UNIT>
+where
+    ID: Copy + Clone + core::fmt::Debug,
+    UNIT: Copy + Clone + core::fmt::Debug + PartialEq,
+{
+    match (ft, action) {
+        // Only SFID1/EFID1 is matched when storing into a Rx buffer
+        #[cfg(feature = "fdcan_h7")]
+        (_, Action::StoreInRxBuffer(_)) => FilterType::DedicatedSingle(to_id(id1)),
+        (PacFilterType::RangeFilter, _) => FilterType::Range {
+            from: to_id(id1),
+            to: to_id(id2),
+        },
+        (PacFilterType::DualIdFilter, _) if id1 == id2 => {
+            FilterType::DedicatedSingle(to_id(id1))
+        }
+        (PacFilterType::DualIdFilter, _) => {
+            FilterType::DedicatedDual(to_id(id1), to_id(id2))
+        }
+        (PacFilterType::ClassicFilter, _) => FilterType::BitMask {
+            filter: id1,
+            mask: id2,
+        },
+        (PacFilterType::FilterDisabled, _) => FilterType::Disabled,
+    }
+}
 
 impl ActivateFilter<StandardId, u16> for message_ram::StandardFilter {
     fn activate(&mut self, f: Filter<StandardId, u16>) {
         let sft = f.filter.into();
 
         let (sfid1, sfid2) = match f.filter {
-            FilterType::Range { to, from } => (to.as_raw(), from.as_raw()),
+            FilterType::Range { from, to } => (from.as_raw(), to.as_raw()),
             FilterType::DedicatedSingle(id) => (id.as_raw(), id.as_raw()),
             FilterType::DedicatedDual(id1, id2) => (id1.as_raw(), id2.as_raw()),
             FilterType::BitMask { filter, mask } => (filter, mask),
             FilterType::Disabled => (0x0, 0x0),
         };
+        // SFID2[10:9] = 0b00 stores into a Rx buffer, SFID2[5:0] is the buffer index
+        #[cfg(feature = "fdcan_h7")]
+        let sfid2 = match f.action {
+            Action::StoreInRxBuffer(idx) => u16::from(idx & 0x3f),
+            _ => sfid2,
+        };
         let sfec = f.action.into();
         self.write(|w| {
             unsafe { w.sfid1().bits(sfid1).sfid2().bits(sfid2) }
@@ -365,21 +397,35 @@ impl ActivateFilter<StandardId, u16> for message_ram::StandardFilter {
                 .set_filter_element_config(sfec)
         });
     }
-    // fn read(&self) -> Filter<StandardId, u16> {
-    //     todo!()
-    // }
+    fn read_filter(&self) -> Filter<StandardId, u16> {
+        let r = self.read();
+        let ids = (r.sfid1().bits(), r.sfid2().bits());
+        let action = read_action(r.sfec().to_filter_element_config(), ids.1.into());
+        // Safety: The ids are masked to 11 bits
+        let filter =
+            read_filter_type(r.sft().to_filter_type(), action, ids, |id| unsafe {
+                StandardId::new_unchecked(id)
+            });
+        Filter { filter, action }
+    }
 }
 impl ActivateFilter<ExtendedId, u32> for message_ram::ExtendedFilter {
     fn activate(&mut self, f: Filter<ExtendedId, u32>) {
         let eft = f.filter.into();
 
         let (efid1, efid2) = match f.filter {
-            FilterType::Range { to, from } => (to.as_raw(), from.as_raw()),
+            FilterType::Range { from, to } => (from.as_raw(), to.as_raw()),
             FilterType::DedicatedSingle(id) => (id.as_raw(), id.as_raw()),
             FilterType::DedicatedDual(id1, id2) => (id1.as_raw(), id2.as_raw()),
             FilterType::BitMask { filter, mask } => (filter, mask),
             FilterType::Disabled => (0x0, 0x0),
         };
+        // EFID2[10:9] = 0b00 stores into a Rx buffer, EFID2[5:0] is the buffer index
+        #[cfg(feature = "fdcan_h7")]
+        let efid2 = match f.action {
+            Action::StoreInRxBuffer(idx) => u32::from(idx & 0x3f),
+            _ => efid2,
+        };
         let efec = f.action.into();
         self.write(|w| {
             unsafe { w.efid1().bits(efid1).efid2().bits(efid2) }
@@ -389,7 +435,70 @@ impl ActivateFilter<ExtendedId, u32> for message_ram::ExtendedFilter {
                 .set_filter_element_config(efec)
         });
     }
-    // fn read(&self) -> Filter<ExtendedId, u32> {
-    //     todo!()
-    // }
+    fn read_filter(&self) -> Filter<ExtendedId, u32> {
+        let r = self.read();
+        let ids = (r.sfid1().bits(), r.sfid2().bits());
+        let action = read_action(r.efec().to_filter_element_config(), ids.1);
+        // Safety: The ids are masked to 29 bits
+        let filter =
+            read_filter_type(r.eft().to_filter_type(), action, ids, |id| unsafe {
+                ExtendedId::new_unchecked(id)
+            });
+        Filter { filter, action }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_range_round_trip() {
+        let (from, to) = (
+            StandardId::new(0x100).unwrap(),
+            StandardId::new(0x1ff).unwrap(),
+        );
+        let mut word = 0_u32;
+        // Safety: a filter element is a single word in the Message RAM
+        let element = unsafe {
+            &mut *(&mut word as *mut u32 as *mut message_ram::StandardFilter)
+        };
+
+        element.activate(Filter {
+            filter: FilterType::Range { from, to },
+            action: Action::StoreInFifo0,
+        });
+        // SFID1 holds the first ID of the range, SFID2 the last one
+        assert_eq!((word >> 16) & 0x7ff, 0x100);
+        assert_eq!(word & 0x7ff, 0x1ff);
+        assert!(matches!(
+            element.read_filter().filter,
+            FilterType::Range { from: f, to: t } if f == from && t == to
+        ));
+    }
+
+    #[test]
+    fn extended_range_round_trip() {
+        let (from, to) = (
+            ExtendedId::new(0x1234).unwrap(),
+            ExtendedId::new(0x1_0000).unwrap(),
+        );
+        let mut words = [0_u32; 2];
+        // Safety: a filter element is two words in the Message RAM
+        let element = unsafe {
+            &mut *(&mut words as *mut [u32; 2] as *mut message_ram::ExtendedFilter)
+        };
+
+        element.activate(Filter {
+            filter: FilterType::Range { from, to },
+            action: Action::StoreInFifo1,
+        });
+        // EFID1 holds the first ID of the range, EFID2 the last one
+        assert_eq!(words[0] & 0x1fff_ffff, 0x1234);
+        assert_eq!(words[1] & 0x1fff_ffff, 0x1_0000);
+        assert!(matches!(
+            element.read_filter().filter,
+            FilterType::Range { from: f, to: t } if f == from && t == to
+        ));
+    }
 }
@@ -0,0 +1,587 @@
+use super::{Action, Filter, FilterType};
+use crate::id::{ExtendedId, StandardId};
+
+/// Identifiers that can be used in a [`FilterSet`]
+pub trait FilterSetId: Copy + core::fmt::Debug + crate::sealed::Sealed {
+    /// Type of the filter and mask of a [`FilterType::BitMask`]
+    type Unit: Copy + core::fmt::Debug + Into<u32>;
+
+    #[doc(hidden)]
+    const MASK: u32;
+    #[doc(hidden)]
+    fn to_raw(self) -> u32;
+    #[doc(hidden)]
+    fn from_raw(raw: u32) -> Self;
+    #[doc(hidden)]
+    fn unit(raw: u32) -> Self::Unit;
+}
+
+impl crate::sealed::Sealed for StandardId {}
+impl FilterSetId for StandardId {
+    type Unit = u16;
+    const MASK: u32 = 0x7FF;
+    fn to_raw(self) -> u32 {
+        self.as_raw().into()
+    }
+    fn from_raw(raw: u32) -> Self {
+        // Safety: Masked to 11 bits
+        unsafe { StandardId::new_unchecked((raw & Self::MASK) as u16) }
+    }
+    fn unit(raw: u32) -> u16 {
+        (raw & Self::MASK) as u16
+    }
+}
+
+impl crate::sealed::Sealed for ExtendedId {}
+impl FilterSetId for ExtendedId {
+    type Unit = u32;
+    const MASK: u32 = 0x1FFF_FFFF;
+    fn to_raw(self) -> u32 {
+        self.as_raw()
+    }
+    fn from_raw(raw: u32) -> Self {
+        // Safety: Masked to 29 bits
+        unsafe { ExtendedId::new_unchecked(raw & Self::MASK) }
+    }
+    fn unit(raw: u32) -> u32 {
+        raw & Self::MASK
+    }
+}
+
+/// Rx FIFO that a [`FilterSet`] rule stores matching frames in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RxFifo {
+    /// Store matching frames in FIFO 0
+    Fifo0,
+    /// Store matching frames in FIFO 1
+    Fifo1,
+}
+impl From<RxFifo> for Action {
+    fn from(fifo: RxFifo) -> Self {
+        match fifo {
+            RxFifo::Fifo0 => Action::StoreInFifo0,
+            RxFifo::Fifo1 => Action::StoreInFifo1,
+        }
+    }
+}
+
+/// Error compiling a [`FilterSet`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FilterSetError {
+    /// The set needs more filter elements than available
+    Overflow {
+        /// Number of filter elements needed
+        required: usize,
+        /// Number of filter elements available
+        available: usize,
+    },
+}
+
+/// A Standard Filter Set
+pub type StandardFilterSet<const N: usize> = FilterSet<StandardId, N>;
+/// An Extended Filter Set
+pub type ExtendedFilterSet<const N: usize> = FilterSet<ExtendedId, N>;
+
+#[derive(Clone, Copy, Debug)]
+enum Rule {
+    Range(u32, u32),
+    Mask(u32, u32),
+}
+
+/// A set of accepted IDs, ranges and masks, each with a target FIFO, holding up to `N`
+/// rules.
+///
+/// [`FilterSet::compile`] maps the set onto filter elements: adjacent and overlapping IDs and
+/// ranges are merged, and single IDs are packed in pairs into [`FilterType::DedicatedDual`]
+/// filters. The filters for FIFO 0 come first and take precedence where rules for both FIFOs
+/// overlap. Frames not matching any rule are handled by the [`GlobalFilter`].
+///
+/// ```ignore
+/// let mut filters = [StandardFilter::disable(); STANDARD_FILTER_MAX as usize];
+/// StandardFilterSet::<8>::new()
+///     .accept_id(StandardId::new(0x100).unwrap(), RxFifo::Fifo0)
+///     .accept_id(StandardId::new(0x101).unwrap(), RxFifo::Fifo0)
+///     .accept_range(
+///         StandardId::new(0x200).unwrap(),
+///         StandardId::new(0x2FF).unwrap(),
+///         RxFifo::Fifo1,
+///     )
+///     .compile(&mut filters)?;
+/// can.set_standard_filters(&filters);
+/// ```
+///
+/// [`GlobalFilter`]: crate::config::GlobalFilter
+#[derive(Clone, Copy, Debug)]
+pub struct FilterSet<ID: FilterSetId, const N: usize> {
+    rules: [(Rule, RxFifo); N],
+    len: usize,
+    _id: core::marker::PhantomData<ID>,
+}
+
+impl<ID: FilterSetId, const N: usize> Default for FilterSet<ID, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<ID: FilterSetId, const N: usize> FilterSet<ID, N> {
+    /// Creates an empty set
+    pub fn new() -> Self {
+        Self {
+            rules: [(Rule::Mask(0, 0), RxFifo::Fifo0); N],
+            len: 0,
+            _id: core::marker::PhantomData,
+        }
+    }
+
+    fn push(mut self, rule: Rule, fifo: RxFifo) -> Self {
+        assert!(self.len < N, "FilterSet is full");
+        self.rules[self.len] = (rule, fifo);
+        self.len += 1;
+        self
+    }
+
+    /// Accepts frames with `id`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set already holds `N` rules.
+    pub fn accept_id(self, id: ID, fifo: RxFifo) -> Self {
+        self.push(Rule::Range(id.to_raw(), id.to_raw()), fifo)
+    }
+
+    /// Accepts frames with an ID from `from` to `to`, inclusive
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set already holds `N` rules.
+    pub fn accept_range(self, from: ID, to: ID, fifo: RxFifo) -> Self {
+        let (from, to) = (from.to_raw(), to.to_raw());
+        self.push(Rule::Range(from.min(to), from.max(to)), fifo)
+    }
+
+    /// Accepts frames with an ID that matches `filter` in all bits set in `mask`
+    ///
+    /// # Panics
+    ///
+    /// Panics if the set already holds `N` rules.
+    pub fn accept_mask(self, filter: ID::Unit, mask: ID::Unit, fifo: RxFifo) -> Self {
+        let mask = mask.into() & ID::MASK;
+        self.push(Rule::Mask(filter.into() & mask, mask), fifo)
+    }
+
+    /// Compiles the set into `filters`, one filter element per slot. Unused slots are
+    /// disabled. Returns the number of used slots.
+    ///
+    /// Returns [`FilterSetError::Overflow`] if the set does not fit.
+    pub fn compile(
+        &self,
+        filters: &mut [Filter<ID, ID::Unit>],
+    ) -> Result<usize, FilterSetError> {
+        self.compile_inner(filters, false)
+    }
+
+    /// Compiles the set like [`FilterSet::compile`]. When the set does not fit, the closest
+    /// ranges are merged and finally each FIFO falls back to a single mask. The filters then
+    /// accept a superset of the IDs, which need to be filtered in software.
+    ///
+    /// Returns [`FilterSetError::Overflow`] only if there are fewer slots than FIFOs used.
+    pub fn compile_superset(
+        &self,
+        filters: &mut [Filter<ID, ID::Unit>],
+    ) -> Result<usize, FilterSetError> {
+        self.compile_inner(filters, true)
+    }
+
+    fn compile_inner(
+        &self,
+        filters: &mut [Filter<ID, ID::Unit>],
+        superset: bool,
+    ) -> Result<usize, FilterSetError> {
+        let mut fifos = [Elements::<N>::new(), Elements::<N>::new()];
+        for &(rule, fifo) in &self.rules[..self.len] {
+            fifos[fifo as usize].push(rule);
+        }
+        for elements in &mut fifos {
+            elements.normalize();
+        }
+
+        let available = filters.len();
+        let required = |fifos: &[Elements<N>; 2]| fifos[0].count() + fifos[1].count();
+        if superset {
+            while required(&fifos) > available {
+                let gaps = [fifos[0].closest(), fifos[1].closest()];
+                let fifo = match gaps {
+                    [Some((_, a)), Some((_, b))] if b < a => 1,
+                    [Some(_), _] => 0,
+                    [None, Some(_)] => 1,
+                    [None, None] => break,
+                };
+                let (idx, _) = gaps[fifo].unwrap();
+                fifos[fifo].merge(idx);
+            }
+            for fifo in [1, 0] {
+                if required(&fifos) > available {
+                    fifos[fifo].collapse::<ID>();
+                }
+            }
+        }
+        let required = required(&fifos);
+        if required > available {
+            return Err(FilterSetError::Overflow {
+                required,
+                available,
+            });
+        }
+
+        let mut slots = filters.iter_mut();
+        for (elements, fifo) in fifos.iter().zip([RxFifo::Fifo0, RxFifo::Fifo1]) {
+            for filter in elements.filters::<ID>() {
+                *slots.next().unwrap() = Filter {
+                    filter,
+                    action: fifo.into(),
+                };
+            }
+        }
+        for slot in slots {
+            *slot = Filter {
+                filter: FilterType::Disabled,
+                action: Action::Disable,
+            };
+        }
+        Ok(required)
+    }
+}
+
+/// Ranges and masks of a single FIFO. Ranges are sorted and disjoint after `normalize`.
+#[derive(Clone, Copy, Debug)]
+struct Elements<const N: usize> {
+    ranges: [(u32, u32); N],
+    num_ranges: usize,
+    masks: [(u32, u32); N],
+    num_masks: usize,
+}
+
+impl<const N: usize> Elements<N> {
+    fn new() -> Self {
+        Self {
+            ranges: [(0, 0); N],
+            num_ranges: 0,
+            masks: [(0, 0); N],
+            num_masks: 0,
+        }
+    }
+
+    fn push(&mut self, rule: Rule) {
+        match rule {
+            Rule::Range(from, to) => {
+                self.ranges[self.num_ranges] = (from, to);
+                self.num_ranges += 1;
+            }
+            Rule::Mask(filter, mask) => {
+                self.masks[self.num_masks] = (filter, mask);
+                self.num_masks += 1;
+            }
+        }
+    }
+
+    /// Sorts and merges the ranges, and drops single IDs that are matched by a mask
+    fn normalize(&mut self) {
+        let ranges = &mut self.ranges[..self.num_ranges];
+        ranges.sort_unstable();
+
+        let mut len: usize = 0;
+        for idx in 0..ranges.len() {
+            let (from, to) = ranges[idx];
+            let masked = from == to
+                && self.masks[..self.num_masks]
+                    .iter()
+                    .any(|&(filter, mask)| from & mask == filter);
+            if masked {
+                continue;
+            }
+            match len.checked_sub(1).map(|last| &mut ranges[last]) {
+                Some(last) if from <= last.1.saturating_add(1) => {
+                    last.1 = last.1.max(to)
+                }
+                _ => {
+                    ranges[len] = (from, to);
+                    len += 1;
+                }
+            }
+        }
+        self.num_ranges = len;
+    }
+
+    /// Number of filter elements, with single IDs packed in pairs
+    fn count(&self) -> usize {
+        let ranges = &self.ranges[..self.num_ranges];
+        let singles = ranges.iter().filter(|(from, to)| from == to).count();
+        (ranges.len() - singles) + singles / 2 + singles % 2 + self.num_masks
+    }
+
+    /// Returns the index of the range with the smallest gap to its successor, and the gap
+    fn closest(&self) -> Option<(usize, u32)> {
+        self.ranges[..self.num_ranges]
+            .windows(2)
+            .map(|pair| pair[1].0 - pair[0].1)
+            .enumerate()
+            .min_by_key(|&(_, gap)| gap)
+    }
+
+    /// Merges the range at `idx` with its successor
+    fn merge(&mut self, idx: usize) {
+        self.ranges[idx].1 = self.ranges[idx + 1].1;
+        self.ranges.copy_within(idx + 2..self.num_ranges, idx + 1);
+        self.num_ranges -= 1;
+    }
+
+    /// Replaces all ranges and masks with a single mask matching all of them
+    fn collapse<ID: FilterSetId>(&mut self) {
+        let ranges = self.ranges[..self.num_ranges].iter().map(|&(from, to)| {
+            // Keep the bits above the highest bit that differs within the range
+            let differ = from ^ to;
+            let mask = match 32 - differ.leading_zeros() {
+                32 => 0,
+                bits => !((1 << bits) - 1),
+            };
+            (from & mask, mask)
+        });
+        let collapsed = ranges
+            .chain(self.masks[..self.num_masks].iter().copied())
+            .fold(None, |acc: Option<(u32, u32)>, (filter, mask)| match acc {
+                None => Some((filter, mask)),
+                Some((acc_filter, acc_mask)) => {
+                    let mask = acc_mask & mask & !(acc_filter ^ filter);
+                    Some((filter & mask, mask))
+                }
+            });
+        if let Some((filter, mask)) = collapsed {
+            self.masks[0] = (filter, mask & ID::MASK);
+            self.num_masks = 1;
+            self.num_ranges = 0;
+        }
+    }
+
+    fn filters<ID: FilterSetId>(
+        &self,
+    ) -> impl Iterator<Item = FilterType<ID, ID::Unit>> + '_ {
+        let masks = self.masks[..self.num_masks].iter().map(|&(filter, mask)| {
+            FilterType::BitMask {
+                filter: ID::unit(filter),
+                mask: ID::unit(mask),
+            }
+        });
+        let ranges = self.ranges[..self.num_ranges]
+            .iter()
+            .filter(|(from, to)| from != to)
+            .map(|&(from, to)| FilterType::Range {
+                from: ID::from_raw(from),
+                to: ID::from_raw(to),
+            });
+        let mut singles = self.ranges[..self.num_ranges]
+            .iter()
+            .filter(|(from, to)| from == to)
+            .map(|&(id, _)| ID::from_raw(id));
+        let pairs = core::iter::from_fn(move || {
+            let first = singles.next()?;
+            Some(match singles.next() {
+                Some(second) => FilterType::DedicatedDual(first, second),
+                None => FilterType::DedicatedSingle(first),
+            })
+        });
+        masks.chain(ranges).chain(pairs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::StandardFilter;
+
+    fn id(raw: u16) -> StandardId {
+        StandardId::new(raw).unwrap()
+    }
+
+    fn rule_matches(rule: Rule, id: u32) -> bool {
+        match rule {
+            Rule::Range(from, to) => from <= id && id <= to,
+            Rule::Mask(filter, mask) => id & mask == filter,
+        }
+    }
+
+    /// FIFO the rules of `set` store `id` in, FIFO 0 taking precedence
+    fn expected<const N: usize>(set: &StandardFilterSet<N>, id: u16) -> Option<RxFifo> {
+        let rules = &set.rules[..set.len];
+        [RxFifo::Fifo0, RxFifo::Fifo1]
+            .iter()
+            .copied()
+            .find(|&fifo| {
+                rules
+                    .iter()
+                    .any(|&(rule, f)| f == fifo && rule_matches(rule, id.into()))
+            })
+    }
+
+    /// FIFO the first matching filter stores `id` in
+    fn accepted(filters: &[StandardFilter], id: u16) -> Option<RxFifo> {
+        filters.iter().find_map(|f| {
+            let matched = match f.filter {
+                FilterType::Range { from, to } => {
+                    from.as_raw() <= id && id <= to.as_raw()
+                }
+                FilterType::DedicatedSingle(a) => a.as_raw() == id,
+                FilterType::DedicatedDual(a, b) => a.as_raw() == id || b.as_raw() == id,
+                FilterType::BitMask { filter, mask } => id & mask == filter & mask,
+                FilterType::Disabled => false,
+            };
+            match f.action {
+                Action::StoreInFifo0 if matched => Some(RxFifo::Fifo0),
+                Action::StoreInFifo1 if matched => Some(RxFifo::Fifo1),
+                _ => None,
+            }
+        })
+    }
+
+    #[test]
+    fn normalize_merges_ranges() {
+        let mut elements = Elements::<8>::new();
+        for rule in [
+            Rule::Range(11, 15),
+            Rule::Range(5, 10),
+            Rule::Range(2, 6),
+            Rule::Range(1, 3),
+            Rule::Range(20, 20),
+            Rule::Range(20, 20),
+            Rule::Range(0x42, 0x42),
+            Rule::Mask(0x40, 0x7f0),
+        ] {
+            elements.push(rule);
+        }
+        elements.normalize();
+        // Overlapping and adjacent ranges merge, a single ID matched by a mask is dropped
+        assert_eq!(
+            &elements.ranges[..elements.num_ranges],
+            &[(1, 15), (20, 20)]
+        );
+        assert_eq!(elements.count(), 3);
+    }
+
+    #[test]
+    fn compile_exact() {
+        let set = StandardFilterSet::<8>::new()
+            .accept_id(id(0x100), RxFifo::Fifo0)
+            .accept_id(id(0x101), RxFifo::Fifo0)
+            .accept_id(id(0x110), RxFifo::Fifo0)
+            .accept_id(id(0x120), RxFifo::Fifo0)
+            .accept_id(id(0x130), RxFifo::Fifo0)
+            .accept_range(id(0x2ff), id(0x200), RxFifo::Fifo1)
+            .accept_id(id(0x250), RxFifo::Fifo0)
+            .accept_mask(0x400, 0x700, RxFifo::Fifo1);
+
+        let mut filters = [StandardFilter::disable(); 8];
+        assert_eq!(set.compile(&mut filters), Ok(5));
+        // Single IDs are packed in pairs after the ranges
+        assert!(matches!(
+            filters[0].filter,
+            FilterType::Range { from, to } if from == id(0x100) && to == id(0x101)
+        ));
+        assert!(matches!(
+            filters[1].filter,
+            FilterType::DedicatedDual(a, b) if a == id(0x110) && b == id(0x120)
+        ));
+        assert!(matches!(
+            filters[2].filter,
+            FilterType::DedicatedDual(a, b) if a == id(0x130) && b == id(0x250)
+        ));
+        assert!(matches!(
+            filters[3].filter,
+            FilterType::BitMask {
+                filter: 0x400,
+                mask: 0x700
+            }
+        ));
+        assert!(matches!(filters[5].filter, FilterType::Disabled));
+        for raw in 0..0x800 {
+            assert_eq!(
+                accepted(&filters, raw),
+                expected(&set, raw),
+                "ID {:#x}",
+                raw
+            );
+        }
+
+        assert_eq!(
+            set.compile(&mut filters[..4]),
+            Err(FilterSetError::Overflow {
+                required: 5,
+                available: 4
+            })
+        );
+    }
+
+    #[test]
+    fn compile_superset_merges_closest_ranges() {
+        let set = StandardFilterSet::<4>::new()
+            .accept_range(id(0x100), id(0x10f), RxFifo::Fifo0)
+            .accept_range(id(0x112), id(0x11f), RxFifo::Fifo0)
+            .accept_range(id(0x180), id(0x18f), RxFifo::Fifo0)
+            .accept_id(id(0x7ff), RxFifo::Fifo1);
+
+        let mut filters = [StandardFilter::disable(); 3];
+        assert!(set.compile(&mut filters).is_err());
+        assert_eq!(set.compile_superset(&mut filters), Ok(3));
+        // The gap of 0x110..0x111 is the smallest
+        assert!(matches!(
+            filters[0].filter,
+            FilterType::Range { from, to } if from == id(0x100) && to == id(0x11f)
+        ));
+        for raw in 0..0x800 {
+            let expected = expected(&set, raw);
+            let accepted = accepted(&filters, raw);
+            assert!(expected.is_none() || accepted == expected, "ID {:#x}", raw);
+        }
+    }
+
+    #[test]
+    fn compile_superset_collapses_to_mask() {
+        let set = StandardFilterSet::<4>::new()
+            .accept_mask(0x300, 0x7f0, RxFifo::Fifo0)
+            .accept_range(id(0x310), id(0x31f), RxFifo::Fifo0)
+            .accept_id(id(0x100), RxFifo::Fifo1)
+            .accept_id(id(0x104), RxFifo::Fifo1);
+
+        let mut filters = [StandardFilter::disable(); 2];
+        assert_eq!(set.compile_superset(&mut filters), Ok(2));
+        // 0x300..0x30f and 0x310..0x31f collapse into 0x300..0x31f, and FIFO 1 into 0x100..0x107
+        assert!(matches!(
+            filters[0].filter,
+            FilterType::BitMask {
+                filter: 0x300,
+                mask: 0x7e0
+            }
+        ));
+        assert!(matches!(
+            filters[1].filter,
+            FilterType::BitMask {
+                filter: 0x100,
+                mask: 0x7f8
+            }
+        ));
+        for raw in 0..0x800 {
+            let expected = expected(&set, raw);
+            let accepted = accepted(&filters, raw);
+            assert!(expected.is_none() || accepted == expected, "ID {:#x}", raw);
+        }
+        assert_eq!(accepted(&filters, 0x320), None);
+
+        // Each FIFO needs a slot
+        assert_eq!(
+            set.compile_superset(&mut filters[..1]),
+            Err(FilterSetError::Overflow {
+                required: 2,
+                available: 1
+            })
+        );
+    }
+}
@@ -6,7 +6,7 @@ use crate::IdReg;
 use crate::filter::FilterId;
 
 use crate::message_ram::enums::FrameFormat as PacFrameFormat;
-use crate::message_ram::{RxFifoElementHeader, TxBufferElementHeader};
+use crate::message_ram::{RxFifoElementHeader, TxBufferElementHeader, TxEventElement};
 
 use crate::message_ram::enums::RemoteTransmissionRequest;
 use crate::message_ram::enums::{DataLength, FilterFrameMatch};
@@ -73,25 +73,30 @@ impl Eq for FramePriority {}
 /// Header of a transmit request
 #[derive(Debug, Copy, Clone)]
 pub struct TxFrameHeader {
-    /// Length of the data in bytes
+    /// Length of the data in bytes, or the requested length of a remote frame
     pub len: u8,
     /// Type of message
     pub frame_format: FrameFormat,
     /// Id
     pub id: Id,
+    /// Transmit a remote frame requesting `len` bytes instead of a data frame
+    pub rtr: bool,
     /// Should we use bit rate switching
     ///
     /// Not that this is a request and if the global frame_transmit is set to ClassicCanOnly
     /// this is ignored.
     pub bit_rate_switching: bool,
-    //pub error_state: Option<()>, //TODO
+    /// Transmit the ESI flag of a FdCan frame recessive, signalling an Error Passive
+    /// transmitter. This is used to forward frames, the flag is recessive anyway when this node
+    /// is Error Passive.
+    pub error_state_indicator: bool,
     ///
     pub marker: Option<u8>,
 }
 impl From<TxFrameHeader> for IdReg {
     fn from(header: TxFrameHeader) -> IdReg {
         let id: IdReg = header.id.into();
-        id.with_rtr(header.len == 0)
+        id.with_rtr(header.rtr)
     }
 }
 
@@ -104,7 +109,7 @@ impl MergeTxFrameHeader for TxBufferElementHeader {
         self.write(|w| {
             unsafe { w.id().bits(id.as_raw_id()) }
                 .rtr()
-                .bit(header.len == 0)
+                .bit(header.rtr)
                 .xtd()
                 .set_id_type(header.id.into())
                 .set_len(DataLength::new(header.len, header.frame_format.into()))
@@ -113,7 +118,8 @@ impl MergeTxFrameHeader for TxBufferElementHeader {
                 .set_format(header.frame_format.into())
                 .brs()
                 .bit(header.bit_rate_switching)
-            //esi.set_error_indicator(//TODO//)
+                .esi()
+                .bit(header.error_state_indicator)
         });
     }
 }
@@ -130,8 +136,9 @@ impl From<&TxBufferElementHeader> for TxFrameHeader {
             len: len.len(),
             frame_format: ff.into(),
             id: IdReg::from_register(id, rtr, xtd).into(),
+            rtr: rtr == RemoteTransmissionRequest::TransmitRemoteFrame,
             bit_rate_switching: reader.brs().is_with_brs(),
-            //error_state: Option<()>, //TODO
+            error_state_indicator: reader.esi().is_error_passive(),
             marker: reader.to_event().into(),
         }
     }
@@ -152,7 +159,8 @@ pub struct RxFrameInfo {
     pub filter_match: Option<FilterId>,
     /// was this received with bit rate switching
     pub bit_rate_switching: bool,
-    //pub error_state: (), //TODO
+    /// ESI flag of a FdCan frame, set when the transmitter was Error Passive
+    pub error_state_indicator: bool,
     /// Time stamp counter
     pub time_stamp: u16,
 }
@@ -163,7 +171,9 @@ impl RxFrameInfo {
             len: self.len,
             frame_format: self.frame_format,
             id: self.id,
+            rtr: self.rtr,
             bit_rate_switching: self.bit_rate_switching,
+            error_state_indicator: self.error_state_indicator,
             marker,
         }
     }
@@ -192,8 +202,261 @@ impl From<&RxFifoElementHeader> for RxFrameInfo {
             rtr: rtr == RemoteTransmissionRequest::TransmitRemoteFrame,
             filter_match: filter,
             bit_rate_switching: reader.brs().is_with_brs(),
+            error_state_indicator: reader.esi().is_error_passive(),
             time_stamp: reader.txts().bits(),
-            //error_state //TODO
         }
     }
 }
+
+/// Type of a Tx Event
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TxEventType {
+    /// The frame was transmitted
+    Transmitted,
+    /// The frame was transmitted in spite of a cancellation request
+    TransmittedDespiteCancellation,
+}
+
+/// Event stored in the Tx Event FIFO when a frame with a marker has been transmitted
+#[derive(Debug, Copy, Clone)]
+pub struct TxEvent {
+    /// Length in bytes
+    pub len: u8,
+    /// Frame Format
+    pub frame_format: FrameFormat,
+    /// Id
+    pub id: Id,
+    /// Is this an Remote Transmit Request
+    pub rtr: bool,
+    /// was this transmitted with bit rate switching
+    pub bit_rate_switching: bool,
+    /// The marker of the transmitted frame, see [`TxFrameHeader::marker`]
+    pub marker: u8,
+    /// Time stamp counter at the start of the transmission
+    pub time_stamp: u16,
+    /// Type of the event
+    pub event_type: TxEventType,
+}
+impl From<&TxEventElement> for TxEvent {
+    fn from(reg: &TxEventElement) -> Self {
+        let reader = reg.read();
+        let len = reader.to_data_length();
+        let ff: PacFrameFormat = len.into();
+        let rtr = reader.rtr().rtr();
+        let xtd = reader.xtd().id_type();
+        let id = IdReg::from_register(reader.id().bits(), rtr, xtd).to_id();
+        // Only 0b10 is "transmitted in spite of cancellation", 0b01 is a regular Tx event
+        let event_type = match reader.efc().bits() {
+            0b10 => TxEventType::TransmittedDespiteCancellation,
+            _ => TxEventType::Transmitted,
+        };
+        TxEvent {
+            len: len.len(),
+            frame_format: ff.into(),
+            id,
+            rtr: rtr == RemoteTransmissionRequest::TransmitRemoteFrame,
+            bit_rate_switching: reader.brs().is_with_brs(),
+            marker: reader.mm().bits(),
+            time_stamp: reader.txts().bits(),
+            event_type,
+        }
+    }
+}
+
+/// An owned CAN frame, consisting of its header and up to 64 bytes of payload
+///
+/// This is mostly useful where frames need to be stored or passed on, for example through
+/// the [`embedded-can`](https://docs.rs/embedded-can) traits.
+#[derive(Clone, Copy, Debug)]
+pub struct Frame {
+    id: Id,
+    rtr: bool,
+    frame_format: FrameFormat,
+    bit_rate_switching: bool,
+    error_state_indicator: bool,
+    len: u8,
+    data: [u8; 64],
+}
+impl Frame {
+    /// Creates a new data frame. Payloads of more than 8 bytes are sent as an FdCan frame.
+    ///
+    /// Returns `None` if `data` is longer than 64 bytes.
+    pub fn new_data(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        let frame_format = match data.len() {
+            0..=8 => FrameFormat::Standard,
+            9..=64 => FrameFormat::Fdcan,
+            _ => return None,
+        };
+        let mut buffer = [0_u8; 64];
+        buffer[..data.len()].copy_from_slice(data);
+        Some(Frame {
+            id: id.into(),
+            rtr: false,
+            frame_format,
+            bit_rate_switching: false,
+            error_state_indicator: false,
+            len: data.len() as u8,
+            data: buffer,
+        })
+    }
+
+    /// Creates a new remote frame requesting `dlc` bytes of data.
+    ///
+    /// Returns `None` if `dlc` is larger than 8.
+    pub fn new_remote(id: impl Into<Id>, dlc: usize) -> Option<Self> {
+        if dlc > 8 {
+            return None;
+        }
+        Some(Frame {
+            id: id.into(),
+            rtr: true,
+            frame_format: FrameFormat::Standard,
+            bit_rate_switching: false,
+            error_state_indicator: false,
+            len: dlc as u8,
+            data: [0; 64],
+        })
+    }
+
+    /// Creates a frame from a received header and its payload
+    pub fn from_rx(info: RxFrameInfo, data: &[u8]) -> Self {
+        let len = if info.rtr { 0 } else { info.len as usize };
+        let mut buffer = [0_u8; 64];
+        buffer[..len].copy_from_slice(&data[..len]);
+        Frame {
+            id: info.id,
+            rtr: info.rtr,
+            frame_format: info.frame_format,
+            bit_rate_switching: info.bit_rate_switching,
+            error_state_indicator: info.error_state_indicator,
+            len: info.len,
+            data: buffer,
+        }
+    }
+
+    /// Creates a frame from a transmit header and the payload words of a Tx buffer
+    pub fn from_tx(header: TxFrameHeader, data: &[u32]) -> Self {
+        let mut buffer = [0_u8; 64];
+        for (bytes, word) in buffer.chunks_mut(4).zip(data.iter()) {
+            bytes.copy_from_slice(&word.to_ne_bytes());
+        }
+        Frame {
+            id: header.id,
+            rtr: header.rtr,
+            frame_format: header.frame_format,
+            bit_rate_switching: header.bit_rate_switching,
+            error_state_indicator: header.error_state_indicator,
+            len: header.len,
+            data: buffer,
+        }
+    }
+
+    /// Requests bit rate switching for this frame. Only has an effect on FdCan frames.
+    #[must_use = "returns a new Frame without modifying `self`"]
+    pub fn with_bit_rate_switching(mut self, brs: bool) -> Self {
+        self.bit_rate_switching = brs;
+        self
+    }
+
+    /// Sets the ESI flag, see [`TxFrameHeader::error_state_indicator`]. Only has an effect on
+    /// FdCan frames.
+    #[must_use = "returns a new Frame without modifying `self`"]
+    pub fn with_error_state_indicator(mut self, esi: bool) -> Self {
+        self.error_state_indicator = esi;
+        self
+    }
+
+    /// Id of this frame
+    #[inline]
+    pub fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Is this an Remote Transmit Request
+    #[inline]
+    pub fn is_remote_frame(&self) -> bool {
+        self.rtr
+    }
+
+    /// Frame Format
+    #[inline]
+    pub fn frame_format(&self) -> FrameFormat {
+        self.frame_format
+    }
+
+    /// ESI flag, set when the transmitter was Error Passive
+    #[inline]
+    pub fn error_state_indicator(&self) -> bool {
+        self.error_state_indicator
+    }
+
+    /// Length of the data in bytes, or the requested length of a remote frame
+    #[inline]
+    pub fn len(&self) -> u8 {
+        self.len
+    }
+
+    /// Returns true if this frame carries no data
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.data().is_empty()
+    }
+
+    /// The payload of this frame. Empty for remote frames.
+    #[inline]
+    pub fn data(&self) -> &[u8] {
+        if self.rtr {
+            &[]
+        } else {
+            &self.data[..self.len as usize]
+        }
+    }
+
+    /// Builds the header used to transmit this frame
+    pub fn tx_header(&self, marker: Option<u8>) -> TxFrameHeader {
+        TxFrameHeader {
+            len: self.len,
+            frame_format: self.frame_format,
+            id: self.id,
+            rtr: self.rtr,
+            bit_rate_switching: self.bit_rate_switching,
+            error_state_indicator: self.error_state_indicator,
+            marker,
+        }
+    }
+
+    /// Returns the priority of this frame
+    #[inline]
+    pub fn priority(&self) -> FramePriority {
+        let id: IdReg = self.id.into();
+        FramePriority(id.with_rtr(self.rtr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::StandardId;
+
+    #[test]
+    fn remote_frames_are_explicit() {
+        let id = StandardId::new(0x123).unwrap();
+
+        // A data frame without data is not a remote frame
+        let header = Frame::new_data(id, &[]).unwrap().tx_header(None);
+        assert_eq!((header.len, header.rtr), (0, false));
+        let frame = Frame::from_tx(header, &[]);
+        assert!(!frame.is_remote_frame());
+
+        // A remote frame keeps its DLC
+        let header = Frame::new_remote(id, 4).unwrap().tx_header(Some(1));
+        assert_eq!((header.len, header.rtr), (4, true));
+        let frame = Frame::from_tx(header, &[]);
+        assert!(frame.is_remote_frame());
+        assert_eq!((frame.len(), frame.data()), (4, &[][..]));
+
+        let data: IdReg = Frame::new_data(id, &[]).unwrap().tx_header(None).into();
+        let remote: IdReg = header.into();
+        assert!(data > remote);
+    }
+}
@@ -0,0 +1,1135 @@
+//! ISO-TP (ISO 15765-2) transport protocol.
+//!
+//! An [`IsoTp`] channel segments messages of up to `N` bytes into single, first and consecutive
+//! frames, and reassembles received frames into messages, with flow control in both directions.
+//! Classic CAN and CAN FD frames (with the escape sequences for longer single frames and
+//! messages) are supported, as well as normal, extended and mixed addressing.
+//!
+//! The channel is driven by the application: received frames are passed to [`IsoTp::on_frame`],
+//! and [`IsoTp::poll`] transmits the pending frames and checks the timeouts. Both take the current
+//! time in microseconds from a free running, wrapping clock:
+//!
+//! ```ignore
+//! let config = IsoTpConfig::new(tx_id, rx_id).set_block_size(8);
+//! let mut isotp: IsoTp<4095> = IsoTp::new(config);
+//!
+//! isotp.send(&request)?;
+//! loop {
+//!     if let Ok(info) = rx.receive(&mut buffer) {
+//!         let event = isotp.on_frame(&info.unwrap(), &buffer, now())?;
+//!         if let Some(IsoTpEvent::Received(len)) = event {
+//!             handle(&isotp.received()[..len]);
+//!         }
+//!     }
+//!     isotp.poll(&mut tx, now())?;
+//! }
+//! ```
+//!
+//! Frames are put into the Tx FIFO/queue with [`Tx::reserve`], so they never replace a pending
+//! frame.
+
+use crate::frame::{FrameFormat, RxFrameInfo, TxFrameHeader};
+use crate::id::Id;
+use crate::message_ram;
+use crate::{Instance, Tx};
+
+/// Largest message length of a first frame without the escape sequence
+const FF_DL_MAX_SHORT: usize = 0xfff;
+
+/// Addressing format of an ISO-TP channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Addressing {
+    /// The CAN identifiers carry the addresses
+    Normal,
+    /// The first data byte carries the target address
+    Extended {
+        /// Target address written into transmitted frames
+        target: u8,
+        /// Own address, expected in received frames
+        source: u8,
+    },
+    /// The first data byte carries the address extension, in both directions
+    Mixed(u8),
+}
+impl Addressing {
+    #[inline]
+    const fn tx_prefix(self) -> Option<u8> {
+        match self {
+            Addressing::Normal => None,
+            Addressing::Extended { target, .. } => Some(target),
+            Addressing::Mixed(ae) => Some(ae),
+        }
+    }
+
+    #[inline]
+    const fn rx_prefix(self) -> Option<u8> {
+        match self {
+            Addressing::Normal => None,
+            Addressing::Extended { source, .. } => Some(source),
+            Addressing::Mixed(ae) => Some(ae),
+        }
+    }
+
+    #[inline]
+    const fn offset(self) -> usize {
+        match self {
+            Addressing::Normal => 0,
+            _ => 1,
+        }
+    }
+}
+
+/// Configuration of an ISO-TP channel
+#[derive(Clone, Copy, Debug)]
+pub struct IsoTpConfig {
+    /// Identifier of transmitted frames
+    pub tx_id: Id,
+    /// Identifier of received frames
+    pub rx_id: Id,
+    /// Addressing format
+    pub addressing: Addressing,
+    /// Frame format of transmitted frames
+    pub frame_format: FrameFormat,
+    /// Length of transmitted frames (TX_DL). 8 for Classic CAN.
+    pub tx_data_length: u8,
+    /// Bit rate switching of transmitted FdCan frames
+    pub bit_rate_switching: bool,
+    /// Byte used to pad frames, or `None` to send frames of Classic CAN length without padding.
+    /// FdCan frames longer than 8 bytes are always padded, with 0xCC by default.
+    pub padding: Option<u8>,
+    /// Number of consecutive frames the sender may send before waiting for the next flow control
+    /// frame. 0 for no limit.
+    pub block_size: u8,
+    /// Minimum separation time between consecutive frames requested from the sender, as encoded
+    /// in the flow control frame: 0x00-0x7F in ms, 0xF1-0xF9 in 100-900 µs
+    pub st_min: u8,
+    /// N_As/N_Ar, the time a frame may wait for a free Tx buffer, in µs
+    pub timeout_a: u32,
+    /// N_Bs, the time the sender waits for a flow control frame, in µs
+    pub timeout_bs: u32,
+    /// N_Cr, the time the receiver waits for the next consecutive frame, in µs
+    pub timeout_cr: u32,
+}
+impl IsoTpConfig {
+    /// Creates a config for Classic CAN with normal addressing and the default timeouts of
+    /// 1000 ms
+    pub const fn new(tx_id: Id, rx_id: Id) -> Self {
+        Self {
+            tx_id,
+            rx_id,
+            addressing: Addressing::Normal,
+            frame_format: FrameFormat::Standard,
+            tx_data_length: 8,
+            bit_rate_switching: false,
+            padding: None,
+            block_size: 0,
+            st_min: 0,
+            timeout_a: 1_000_000,
+            timeout_bs: 1_000_000,
+            timeout_cr: 1_000_000,
+        }
+    }
+
+    /// Sets the addressing format
+    #[inline]
+    pub const fn set_addressing(mut self, addressing: Addressing) -> Self {
+        self.addressing = addressing;
+        self
+    }
+
+    /// Transmits FdCan frames of up to `tx_data_length` bytes
+    ///
+    /// # Panics
+    ///
+    /// Panics if `tx_data_length` is not a valid FdCan frame length of at least 8 bytes.
+    #[inline]
+    pub const fn set_fd(
+        mut self,
+        tx_data_length: u8,
+        bit_rate_switching: bool,
+    ) -> Self {
+        assert!(
+            matches!(tx_data_length, 8 | 12 | 16 | 20 | 24 | 32 | 48 | 64),
+            "Invalid FdCan frame length"
+        );
+        self.frame_format = FrameFormat::Fdcan;
+        self.tx_data_length = tx_data_length;
+        self.bit_rate_switching = bit_rate_switching;
+        self
+    }
+
+    /// Sets the padding byte, see [`IsoTpConfig::padding`]
+    #[inline]
+    pub const fn set_padding(mut self, padding: Option<u8>) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Sets the block size sent in flow control frames
+    #[inline]
+    pub const fn set_block_size(mut self, block_size: u8) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Sets the minimum separation time sent in flow control frames, see
+    /// [`IsoTpConfig::st_min`]
+    #[inline]
+    pub const fn set_st_min(mut self, st_min: u8) -> Self {
+        self.st_min = st_min;
+        self
+    }
+
+    /// Sets the N_As/N_Ar, N_Bs and N_Cr timeouts, in µs
+    #[inline]
+    pub const fn set_timeouts(mut self, a: u32, bs: u32, cr: u32) -> Self {
+        self.timeout_a = a;
+        self.timeout_bs = bs;
+        self.timeout_cr = cr;
+        self
+    }
+}
+
+/// Event of an ISO-TP channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IsoTpEvent {
+    /// A message of this length was received, see [`IsoTp::received`]
+    Received(usize),
+    /// All frames of the message passed to [`IsoTp::send`] were put into the Tx buffers
+    Sent,
+}
+
+/// Errors of an ISO-TP channel. The affected transfer is aborted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IsoTpError {
+    /// A message is already being sent
+    Busy,
+    /// The message is empty or does not fit into the buffer of the channel
+    InvalidLength,
+    /// No Tx buffer became available within N_As/N_Ar
+    TimeoutA,
+    /// No flow control frame was received within N_Bs
+    TimeoutBs,
+    /// No consecutive frame was received within N_Cr
+    TimeoutCr,
+    /// A consecutive frame with an unexpected sequence number was received
+    WrongSequenceNumber,
+    /// The receiver reported that the message does not fit into its buffer
+    Overflow,
+    /// A frame with an invalid protocol control information or length was received
+    InvalidFrame,
+    /// [`IsoTpConfig::tx_data_length`] is larger than the data field of the Tx buffers in the
+    /// Message RAM layout
+    TxDataLength,
+}
+
+/// Flow status of a flow control frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FlowStatus {
+    ContinueToSend = 0,
+    Overflow = 2,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum TxState {
+    Idle,
+    /// The single or first frame is pending
+    Start,
+    WaitFlowControl {
+        deadline: u32,
+    },
+    /// Consecutive frames are pending, `block` is the number of frames left in this block, 0 for
+    /// no limit
+    Consecutive {
+        next: u32,
+        block: u8,
+        separation: u32,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+enum RxState {
+    Idle,
+    FlowControl(FlowStatus),
+    Consecutive { deadline: u32 },
+}
+
+/// Returns `true` if `deadline` has been reached
+#[inline]
+fn expired(now: u32, deadline: u32) -> bool {
+    now.wrapping_sub(deadline) as i32 >= 0
+}
+
+/// Notes that a frame could not be transmitted, returns `true` if it has been waiting for longer
+/// than `timeout`
+#[inline]
+fn blocked(since: &mut Option<u32>, now: u32, timeout: u32) -> bool {
+    match *since {
+        Some(since) => now.wrapping_sub(since) > timeout,
+        None => {
+            *since = Some(now);
+            false
+        }
+    }
+}
+
+/// Decodes a separation time into µs. Reserved values are treated as 127 ms.
+const fn separation_time(st_min: u8) -> u32 {
+    match st_min {
+        0x00..=0x7f => st_min as u32 * 1000,
+        0xf1..=0xf9 => (st_min - 0xf0) as u32 * 100,
+        _ => 0x7f * 1000,
+    }
+}
+
+/// Rounds `len` up to the next valid FdCan frame length
+const fn fd_frame_length(len: usize) -> usize {
+    match len {
+        0..=8 => len,
+        9..=12 => 12,
+        13..=16 => 16,
+        17..=20 => 20,
+        21..=24 => 24,
+        25..=32 => 32,
+        33..=48 => 48,
+        _ => 64,
+    }
+}
+
+/// An ISO-TP channel for messages of up to `N` bytes
+pub struct IsoTp<const N: usize> {
+    config: IsoTpConfig,
+    tx_state: TxState,
+    tx_buffer: [u8; N],
+    tx_len: usize,
+    tx_pos: usize,
+    tx_sn: u8,
+    tx_blocked: Option<u32>,
+    rx_state: RxState,
+    rx_buffer: [u8; N],
+    rx_len: usize,
+    rx_pos: usize,
+    rx_sn: u8,
+    rx_dl: usize,
+    rx_block: u8,
+    rx_blocked: Option<u32>,
+    received: usize,
+}
+
+impl<const N: usize> IsoTp<N> {
+    /// Creates an idle channel
+    pub const fn new(config: IsoTpConfig) -> Self {
+        Self {
+            config,
+            tx_state: TxState::Idle,
+            tx_buffer: [0; N],
+            tx_len: 0,
+            tx_pos: 0,
+            tx_sn: 0,
+            tx_blocked: None,
+            rx_state: RxState::Idle,
+            rx_buffer: [0; N],
+            rx_len: 0,
+            rx_pos: 0,
+            rx_sn: 0,
+            rx_dl: 0,
+            rx_block: 0,
+            rx_blocked: None,
+            received: 0,
+        }
+    }
+
+    /// Returns the configuration of this channel
+    #[inline]
+    pub fn config(&self) -> &IsoTpConfig {
+        &self.config
+    }
+
+    /// Aborts the transfers in both directions
+    pub fn reset(&mut self) {
+        self.tx_state = TxState::Idle;
+        self.tx_blocked = None;
+        self.rx_state = RxState::Idle;
+        self.rx_blocked = None;
+    }
+
+    /// Returns `true` while a message is being sent
+    #[inline]
+    pub fn is_sending(&self) -> bool {
+        !matches!(self.tx_state, TxState::Idle)
+    }
+
+    /// The last received message. It is overwritten as soon as the next message starts.
+    #[inline]
+    pub fn received(&self) -> &[u8] {
+        &self.rx_buffer[..self.received]
+    }
+
+    /// Starts sending `data`. The frames are transmitted by [`IsoTp::poll`].
+    pub fn send(&mut self, data: &[u8]) -> Result<(), IsoTpError> {
+        if self.is_sending() {
+            return Err(IsoTpError::Busy);
+        }
+        if data.is_empty() || data.len() > N {
+            return Err(IsoTpError::InvalidLength);
+        }
+        self.tx_buffer[..data.len()].copy_from_slice(data);
+        self.tx_len = data.len();
+        self.tx_pos = 0;
+        self.tx_sn = 0;
+        self.tx_blocked = None;
+        self.tx_state = TxState::Start;
+        Ok(())
+    }
+
+    /// Processes a received frame. Frames with other identifiers or addresses are ignored.
+    ///
+    /// `data` is the buffer passed to [`Rx::receive`](crate::Rx::receive).
+    pub fn on_frame(
+        &mut self,
+        info: &RxFrameInfo,
+        data: &[u8],
+        now: u32,
+    ) -> Result<Option<IsoTpEvent>, IsoTpError> {
+        let o = self.config.addressing.offset();
+        let len = info.len as usize;
+        if info.id != self.config.rx_id || info.rtr || len <= o || data.len() < len {
+            return Ok(None);
+        }
+        let data = &data[..len];
+        if let Some(address) = self.config.addressing.rx_prefix() {
+            if data[0] != address {
+                return Ok(None);
+            }
+        }
+
+        match data[o] >> 4 {
+            0 => self.on_single_frame(data),
+            1 => self.on_first_frame(data),
+            2 => self.on_consecutive_frame(data, now),
+            3 => self.on_flow_control(data, now),
+            _ => Ok(None),
+        }
+    }
+
+    /// Transmits the pending frames and checks the timeouts.
+    ///
+    /// Returns [`IsoTpError::TxDataLength`], and aborts the message passed to [`IsoTp::send`], if
+    /// frames of [`IsoTpConfig::tx_data_length`] do not fit into the Tx buffers.
+    pub fn poll<I, MODE>(
+        &mut self,
+        tx: &mut Tx<I, MODE>,
+        now: u32,
+    ) -> Result<Option<IsoTpEvent>, IsoTpError>
+    where
+        I: Instance,
+    {
+        if let RxState::Consecutive { deadline } = self.rx_state {
+            if expired(now, deadline) {
+                self.rx_state = RxState::Idle;
+                return Err(IsoTpError::TimeoutCr);
+            }
+        }
+        if let TxState::WaitFlowControl { deadline } = self.tx_state {
+            if expired(now, deadline) {
+                self.tx_state = TxState::Idle;
+                return Err(IsoTpError::TimeoutBs);
+            }
+        }
+
+        if let RxState::FlowControl(status) = self.rx_state {
+            let (frame, len) = self.flow_control_frame(status);
+            if self.transmit(tx, &frame, len) {
+                self.flow_control_sent(status, now);
+            } else if blocked(&mut self.rx_blocked, now, self.config.timeout_a) {
+                self.rx_state = RxState::Idle;
+                self.rx_blocked = None;
+                return Err(IsoTpError::TimeoutA);
+            }
+        }
+
+        if let TxState::Start = self.tx_state {
+            let data_words = message_ram::tx_buffer_data_words::<I>();
+            if usize::from(self.config.tx_data_length) > data_words * 4 {
+                self.tx_state = TxState::Idle;
+                return Err(IsoTpError::TxDataLength);
+            }
+        }
+
+        loop {
+            let (frame, len, count) = match self.tx_state {
+                TxState::Start => self.start_frame(),
+                TxState::Consecutive { next, .. } if expired(now, next) => {
+                    self.consecutive_frame()
+                }
+                _ => return Ok(None),
+            };
+            if !self.transmit(tx, &frame, len) {
+                if blocked(&mut self.tx_blocked, now, self.config.timeout_a) {
+                    self.tx_state = TxState::Idle;
+                    self.tx_blocked = None;
+                    return Err(IsoTpError::TimeoutA);
+                }
+                return Ok(None);
+            }
+            if let Some(event) = self.frame_sent(count, now) {
+                return Ok(Some(event));
+            }
+        }
+    }
+
+    /// Advances the reception after the flow control frame was transmitted
+    fn flow_control_sent(&mut self, status: FlowStatus, now: u32) {
+        self.rx_blocked = None;
+        self.rx_state = match status {
+            FlowStatus::Overflow => RxState::Idle,
+            _ => RxState::Consecutive {
+                deadline: now.wrapping_add(self.config.timeout_cr),
+            },
+        };
+    }
+
+    /// Advances the transmission after a frame with `count` payload bytes was transmitted
+    fn frame_sent(&mut self, count: usize, now: u32) -> Option<IsoTpEvent> {
+        self.tx_blocked = None;
+        self.tx_pos += count;
+        self.tx_sn = (self.tx_sn + 1) & 0xf;
+
+        self.tx_state = match self.tx_state {
+            TxState::Start if self.tx_pos == self.tx_len => TxState::Idle,
+            TxState::Start => TxState::WaitFlowControl {
+                deadline: now.wrapping_add(self.config.timeout_bs),
+            },
+            TxState::Consecutive { .. } if self.tx_pos == self.tx_len => TxState::Idle,
+            TxState::Consecutive { block: 1, .. } => TxState::WaitFlowControl {
+                deadline: now.wrapping_add(self.config.timeout_bs),
+            },
+            TxState::Consecutive {
+                block, separation, ..
+            } => TxState::Consecutive {
+                next: now.wrapping_add(separation),
+                block: block.saturating_sub(1),
+                separation,
+            },
+            state => state,
+        };
+        match self.tx_state {
+            TxState::Idle => Some(IsoTpEvent::Sent),
+            _ => None,
+        }
+    }
+
+    fn on_single_frame(
+        &mut self,
+        data: &[u8],
+    ) -> Result<Option<IsoTpEvent>, IsoTpError> {
+        let o = self.config.addressing.offset();
+        let (sf_dl, start) = match data[o] & 0xf {
+            // Escape sequence, only valid in FdCan frames
+            0 if data.len() > 8 => (data[o + 1] as usize, o + 2),
+            0 => return Err(IsoTpError::InvalidFrame),
+            sf_dl => (sf_dl as usize, o + 1),
+        };
+        if sf_dl == 0 || start + sf_dl > data.len() {
+            return Err(IsoTpError::InvalidFrame);
+        }
+        // A new message aborts the reception in progress
+        self.rx_state = RxState::Idle;
+        if sf_dl > N {
+            return Err(IsoTpError::InvalidLength);
+        }
+        self.rx_buffer[..sf_dl].copy_from_slice(&data[start..start + sf_dl]);
+        self.received = sf_dl;
+        Ok(Some(IsoTpEvent::Received(sf_dl)))
+    }
+
+    fn on_first_frame(
+        &mut self,
+        data: &[u8],
+    ) -> Result<Option<IsoTpEvent>, IsoTpError> {
+        let o = self.config.addressing.offset();
+        if data.len() < 8 {
+            return Err(IsoTpError::InvalidFrame);
+        }
+        let (ff_dl, start) =
+            match ((data[o] as usize & 0xf) << 8) | data[o + 1] as usize {
+                0 => {
+                    let mut bytes = [0; 4];
+                    bytes.copy_from_slice(&data[o + 2..o + 6]);
+                    (u32::from_be_bytes(bytes) as usize, o + 6)
+                }
+                ff_dl => (ff_dl, o + 2),
+            };
+        if ff_dl <= data.len() - start {
+            return Err(IsoTpError::InvalidFrame);
+        }
+        // A new message aborts the reception in progress
+        self.received = 0;
+        self.rx_blocked = None;
+        if ff_dl > N {
+            self.rx_state = RxState::FlowControl(FlowStatus::Overflow);
+            return Err(IsoTpError::InvalidLength);
+        }
+        let first = data.len() - start;
+        self.rx_buffer[..first].copy_from_slice(&data[start..]);
+        self.rx_len = ff_dl;
+        self.rx_pos = first;
+        self.rx_sn = 1;
+        self.rx_dl = data.len();
+        self.rx_block = self.config.block_size;
+        self.rx_state = RxState::FlowControl(FlowStatus::ContinueToSend);
+        Ok(None)
+    }
+
+    fn on_consecutive_frame(
+        &mut self,
+        data: &[u8],
+        now: u32,
+    ) -> Result<Option<IsoTpEvent>, IsoTpError> {
+        let o = self.config.addressing.offset();
+        if !matches!(self.rx_state, RxState::Consecutive { .. }) {
+            return Ok(None);
+        }
+        if data[o] & 0xf != self.rx_sn {
+            self.rx_state = RxState::Idle;
+            return Err(IsoTpError::WrongSequenceNumber);
+        }
+        let count = (self.rx_len - self.rx_pos).min(self.rx_dl - o - 1);
+        if data.len() < o + 1 + count {
+            self.rx_state = RxState::Idle;
+            return Err(IsoTpError::InvalidFrame);
+        }
+        self.rx_buffer[self.rx_pos..self.rx_pos + count]
+            .copy_from_slice(&data[o + 1..o + 1 + count]);
+        self.rx_pos += count;
+        self.rx_sn = (self.rx_sn + 1) & 0xf;
+
+        if self.rx_pos == self.rx_len {
+            self.rx_state = RxState::Idle;
+            self.received = self.rx_len;
+            return Ok(Some(IsoTpEvent::Received(self.rx_len)));
+        }
+        self.rx_state = if self.rx_block == 1 {
+            self.rx_block = self.config.block_size;
+            RxState::FlowControl(FlowStatus::ContinueToSend)
+        } else {
+            self.rx_block = self.rx_block.saturating_sub(1);
+            RxState::Consecutive {
+                deadline: now.wrapping_add(self.config.timeout_cr),
+            }
+        };
+        Ok(None)
+    }
+
+    fn on_flow_control(
+        &mut self,
+        data: &[u8],
+        now: u32,
+    ) -> Result<Option<IsoTpEvent>, IsoTpError> {
+        let o = self.config.addressing.offset();
+        if !matches!(self.tx_state, TxState::WaitFlowControl { .. }) {
+            return Ok(None);
+        }
+        if data.len() < o + 3 {
+            self.tx_state = TxState::Idle;
+            return Err(IsoTpError::InvalidFrame);
+        }
+        match data[o] & 0xf {
+            0 => {
+                self.tx_state = TxState::Consecutive {
+                    next: now,
+                    block: data[o + 1],
+                    separation: separation_time(data[o + 2]),
+                };
+                Ok(None)
+            }
+            1 => {
+                self.tx_state = TxState::WaitFlowControl {
+                    deadline: now.wrapping_add(self.config.timeout_bs),
+                };
+                Ok(None)
+            }
+            2 => {
+                self.tx_state = TxState::Idle;
+                Err(IsoTpError::Overflow)
+            }
+            _ => {
+                self.tx_state = TxState::Idle;
+                Err(IsoTpError::InvalidFrame)
+            }
+        }
+    }
+
+    /// Builds the single frame, or the first frame of a segmented message. Returns the frame,
+    /// its length and the number of payload bytes.
+    fn start_frame(&self) -> ([u8; 64], usize, usize) {
+        let o = self.config.addressing.offset();
+        let tx_dl = self.config.tx_data_length as usize;
+        let len = self.tx_len;
+        let mut frame = self.frame();
+
+        let (start, count) = if len <= 7 - o {
+            frame[o] = len as u8;
+            (o + 1, len)
+        } else if tx_dl > 8 && len <= tx_dl - o - 2 {
+            frame[o] = 0;
+            frame[o + 1] = len as u8;
+            (o + 2, len)
+        } else if len <= FF_DL_MAX_SHORT {
+            frame[o] = 0x10 | (len >> 8) as u8;
+            frame[o + 1] = len as u8;
+            (o + 2, tx_dl - o - 2)
+        } else {
+            frame[o] = 0x10;
+            frame[o + 1] = 0;
+            frame[o + 2..o + 6].copy_from_slice(&(len as u32).to_be_bytes());
+            (o + 6, tx_dl - o - 6)
+        };
+        frame[start..start + count].copy_from_slice(&self.tx_buffer[..count]);
+        let len = self.pad(&mut frame, start + count);
+        (frame, len, count)
+    }
+
+    /// Builds the next consecutive frame. Returns the frame, its length and the number of
+    /// payload bytes.
+    fn consecutive_frame(&self) -> ([u8; 64], usize, usize) {
+        let o = self.config.addressing.offset();
+        let tx_dl = self.config.tx_data_length as usize;
+        let mut frame = self.frame();
+
+        let count = (self.tx_len - self.tx_pos).min(tx_dl - o - 1);
+        frame[o] = 0x20 | self.tx_sn;
+        frame[o + 1..o + 1 + count]
+            .copy_from_slice(&self.tx_buffer[self.tx_pos..self.tx_pos + count]);
+        let len = self.pad(&mut frame, o + 1 + count);
+        (frame, len, count)
+    }
+
+    fn flow_control_frame(&self, status: FlowStatus) -> ([u8; 64], usize) {
+        let o = self.config.addressing.offset();
+        let mut frame = self.frame();
+        frame[o] = 0x30 | status as u8;
+        frame[o + 1] = self.config.block_size;
+        frame[o + 2] = self.config.st_min;
+        let len = self.pad(&mut frame, o + 3);
+        (frame, len)
+    }
+
+    /// An empty frame with the address prefix
+    fn frame(&self) -> [u8; 64] {
+        let mut frame = [0; 64];
+        if let Some(address) = self.config.addressing.tx_prefix() {
+            frame[0] = address;
+        }
+        frame
+    }
+
+    /// Pads the frame to a valid frame length, returns the padded length
+    fn pad(&self, frame: &mut [u8; 64], len: usize) -> usize {
+        let padded = match self.config.padding {
+            _ if len > 8 => fd_frame_length(len),
+            Some(_) => 8,
+            None => len,
+        };
+        frame[len..padded].fill(self.config.padding.unwrap_or(0xcc));
+        padded
+    }
+
+    /// Puts a frame into a free Tx buffer, returns `false` if there is none
+    fn transmit<I, MODE>(
+        &self,
+        tx: &mut Tx<I, MODE>,
+        frame: &[u8; 64],
+        len: usize,
+    ) -> bool
+    where
+        I: Instance,
+    {
+        let mut slot = match tx.reserve() {
+            Ok(slot) => slot,
+            Err(_) => return false,
+        };
+        slot.set_header(TxFrameHeader {
+            len: len as u8,
+            frame_format: self.config.frame_format,
+            id: self.config.tx_id,
+            rtr: false,
+            bit_rate_switching: self.config.bit_rate_switching,
+            error_state_indicator: false,
+            marker: None,
+        });
+        for (idx, bytes) in frame[..len].chunks(4).enumerate() {
+            let mut word = [0; 4];
+            word[..bytes.len()].copy_from_slice(bytes);
+            slot.write_word(idx, u32::from_ne_bytes(word));
+        }
+        slot.commit();
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::StandardId;
+
+    const MESSAGE_LEN: usize = 4200;
+
+    fn ids() -> (Id, Id) {
+        (
+            StandardId::new(0x7e0).unwrap().into(),
+            StandardId::new(0x7e8).unwrap().into(),
+        )
+    }
+
+    /// Config of the sender, the receiver swaps the identifiers
+    fn config() -> IsoTpConfig {
+        let (tx_id, rx_id) = ids();
+        IsoTpConfig::new(tx_id, rx_id)
+    }
+
+    fn info(id: Id, len: usize) -> RxFrameInfo {
+        RxFrameInfo {
+            len: len as u8,
+            frame_format: FrameFormat::Fdcan,
+            id,
+            rtr: false,
+            filter_match: None,
+            bit_rate_switching: false,
+            error_state_indicator: false,
+            time_stamp: 0,
+        }
+    }
+
+    fn message() -> [u8; MESSAGE_LEN] {
+        let mut data = [0; MESSAGE_LEN];
+        for (i, b) in data.iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        data
+    }
+
+    /// Starts sending `len` bytes and builds the single or first frame
+    fn start(config: IsoTpConfig, len: usize) -> ([u8; 64], usize, usize) {
+        let mut isotp: IsoTp<MESSAGE_LEN> = IsoTp::new(config);
+        isotp.send(&message()[..len]).unwrap();
+        isotp.start_frame()
+    }
+
+    /// Passes the frames between both channels as `poll` would, until the message was
+    /// received. Returns the number of frames sent by `sender`.
+    fn transfer<const N: usize>(
+        sender: &mut IsoTp<N>,
+        receiver: &mut IsoTp<N>,
+        data: &[u8],
+    ) -> usize {
+        sender.send(data).unwrap();
+        let mut now = 0;
+        let mut frames = 0;
+        loop {
+            if let RxState::FlowControl(status) = receiver.rx_state {
+                let (frame, len) = receiver.flow_control_frame(status);
+                receiver.flow_control_sent(status, now);
+                let fc =
+                    sender.on_frame(&info(receiver.config.tx_id, len), &frame, now);
+                assert_eq!(fc, Ok(None));
+            }
+            let (frame, len, count) = match sender.tx_state {
+                TxState::Start => sender.start_frame(),
+                TxState::Consecutive { next, .. } => {
+                    now = next;
+                    sender.consecutive_frame()
+                }
+                state => panic!("Unexpected state {:?}", state),
+            };
+            frames += 1;
+            let sent = sender.frame_sent(count, now);
+            let received = receiver
+                .on_frame(&info(sender.config.tx_id, len), &frame, now)
+                .unwrap();
+            if let Some(IsoTpEvent::Received(len)) = received {
+                assert_eq!(sent, Some(IsoTpEvent::Sent));
+                assert_eq!(receiver.received(), data);
+                assert_eq!(len, data.len());
+                return frames;
+            }
+            assert_eq!(sent, None);
+        }
+    }
+
+    fn round_trip(config: IsoTpConfig, len: usize) -> usize {
+        let (tx_id, rx_id) = ids();
+        let mut sender: IsoTp<MESSAGE_LEN> = IsoTp::new(config);
+        let mut receiver: IsoTp<MESSAGE_LEN> = IsoTp::new(IsoTpConfig {
+            tx_id: rx_id,
+            rx_id: tx_id,
+            addressing: match config.addressing {
+                Addressing::Extended { target, source } => Addressing::Extended {
+                    target: source,
+                    source: target,
+                },
+                addressing => addressing,
+            },
+            ..config
+        });
+        transfer(&mut sender, &mut receiver, &message()[..len])
+    }
+
+    #[test]
+    fn single_frame_boundaries() {
+        // Classic CAN: up to 7 bytes, one less with an address prefix
+        let (frame, len, count) = start(config(), 7);
+        assert_eq!((frame[0], len, count), (0x07, 8, 7));
+        let (frame, _, count) = start(config(), 8);
+        assert_eq!((frame[0], frame[1], count), (0x10, 8, 6));
+
+        let extended = config().set_addressing(Addressing::Extended {
+            target: 0xab,
+            source: 0xcd,
+        });
+        let (frame, len, count) = start(extended, 6);
+        assert_eq!((frame[0], frame[1], len, count), (0xab, 0x06, 8, 6));
+        let (frame, _, count) = start(extended, 7);
+        assert_eq!((frame[0], frame[1], frame[2], count), (0xab, 0x10, 7, 5));
+
+        // CAN FD: the escape sequence carries up to TX_DL - 2 bytes
+        let fd = config().set_fd(64, true);
+        let (frame, len, count) = start(fd, 62);
+        assert_eq!((frame[0], frame[1], len, count), (0x00, 62, 64, 62));
+        let (frame, len, count) = start(fd, 63);
+        assert_eq!((frame[0], frame[1], len, count), (0x10, 63, 64, 62));
+
+        let mixed = fd.set_addressing(Addressing::Mixed(0x42));
+        let (frame, _, count) = start(mixed, 61);
+        assert_eq!((frame[0], frame[1], frame[2], count), (0x42, 0x00, 61, 61));
+        let (frame, _, count) = start(mixed, 62);
+        assert_eq!((frame[1], frame[2], count), (0x10, 62, 61));
+    }
+
+    #[test]
+    fn first_frame_escape() {
+        let (frame, len, count) = start(config(), FF_DL_MAX_SHORT);
+        assert_eq!((&frame[..2], len, count), (&[0x1f, 0xff][..], 8, 6));
+        assert_eq!(&frame[2..8], &message()[..6]);
+
+        let (frame, len, count) = start(config(), FF_DL_MAX_SHORT + 1);
+        assert_eq!(
+            (&frame[..6], len, count),
+            (&[0x10, 0x00, 0x00, 0x00, 0x10, 0x00][..], 8, 2)
+        );
+
+        let (frame, len, count) = start(config().set_fd(64, false), MESSAGE_LEN);
+        assert_eq!(
+            (&frame[..6], len, count),
+            (&[0x10, 0x00, 0x00, 0x00, 0x10, 0x68][..], 64, 58)
+        );
+    }
+
+    #[test]
+    fn padding() {
+        // Classic CAN frames are only padded when a padding byte is set
+        let (_, len, _) = start(config(), 3);
+        assert_eq!(len, 4);
+        let (frame, len, _) = start(config().set_padding(Some(0xaa)), 3);
+        assert_eq!((&frame[4..8], len), (&[0xaa; 4][..], 8));
+
+        // FdCan frames are padded to the next valid length
+        let (frame, len, _) = start(config().set_fd(64, false), 20);
+        assert_eq!((&frame[22..24], len), (&[0xcc; 2][..], 24));
+        let (frame, len, _) =
+            start(config().set_fd(64, false).set_padding(Some(0x55)), 20);
+        assert_eq!((&frame[22..24], len), (&[0x55; 2][..], 24));
+
+        // The last consecutive frame carries 100 - 62 = 38 bytes
+        let mut isotp: IsoTp<MESSAGE_LEN> = IsoTp::new(config().set_fd(64, false));
+        isotp.send(&message()[..100]).unwrap();
+        let (_, _, count) = isotp.start_frame();
+        isotp.frame_sent(count, 0);
+        isotp.tx_state = TxState::Consecutive {
+            next: 0,
+            block: 0,
+            separation: 0,
+        };
+        let (frame, len, count) = isotp.consecutive_frame();
+        assert_eq!((frame[0], count, len), (0x21, 38, 48));
+        assert_eq!(&frame[1..39], &message()[62..100]);
+        assert_eq!(&frame[39..48], &[0xcc; 9]);
+    }
+
+    #[test]
+    fn round_trips() {
+        assert_eq!(round_trip(config(), 7), 1);
+        assert_eq!(round_trip(config(), 8), 2);
+        assert_eq!(round_trip(config().set_padding(Some(0)), 20), 3);
+        assert_eq!(round_trip(config().set_fd(64, true), 62), 1);
+        assert_eq!(round_trip(config().set_fd(64, true), 63), 2);
+        assert_eq!(round_trip(config().set_fd(12, false), 100), 10);
+        assert_eq!(round_trip(config().set_fd(64, true), MESSAGE_LEN), 67);
+        let extended = config().set_addressing(Addressing::Extended {
+            target: 0x12,
+            source: 0x34,
+        });
+        assert_eq!(round_trip(extended.set_block_size(3), 50), 9);
+        let mixed = config().set_addressing(Addressing::Mixed(0x56));
+        assert_eq!(round_trip(mixed.set_block_size(1), 30), 6);
+    }
+
+    #[test]
+    fn first_frame_overflow() {
+        let (tx_id, rx_id) = ids();
+        let mut receiver: IsoTp<16> = IsoTp::new(IsoTpConfig::new(rx_id, tx_id));
+        let ff = [0x10, 100, 0, 1, 2, 3, 4, 5];
+        assert_eq!(
+            receiver.on_frame(&info(tx_id, 8), &ff, 0),
+            Err(IsoTpError::InvalidLength)
+        );
+        assert!(matches!(
+            receiver.rx_state,
+            RxState::FlowControl(FlowStatus::Overflow)
+        ));
+        let (frame, len) = receiver.flow_control_frame(FlowStatus::Overflow);
+        assert_eq!(&frame[..len], &[0x32, 0, 0]);
+        receiver.flow_control_sent(FlowStatus::Overflow, 0);
+        assert!(matches!(receiver.rx_state, RxState::Idle));
+
+        // The sender aborts on FC(Overflow)
+        let mut sender: IsoTp<128> = IsoTp::new(config());
+        sender.send(&message()[..100]).unwrap();
+        let (_, _, count) = sender.start_frame();
+        sender.frame_sent(count, 0);
+        assert_eq!(
+            sender.on_frame(&info(rx_id, 3), &frame[..3], 0),
+            Err(IsoTpError::Overflow)
+        );
+        assert!(!sender.is_sending());
+    }
+
+    #[test]
+    fn block_size_and_st_min() {
+        let (tx_id, rx_id) = ids();
+        let mut receiver: IsoTp<128> = IsoTp::new(
+            IsoTpConfig::new(rx_id, tx_id)
+                .set_block_size(2)
+                .set_st_min(0xf5),
+        );
+        let mut sender: IsoTp<128> = IsoTp::new(config());
+        sender.send(&message()[..30]).unwrap();
+
+        let (frame, len, count) = sender.start_frame();
+        assert_eq!(sender.frame_sent(count, 0), None);
+        assert!(matches!(
+            sender.tx_state,
+            TxState::WaitFlowControl {
+                deadline: 1_000_000
+            }
+        ));
+        receiver.on_frame(&info(tx_id, len), &frame, 0).unwrap();
+
+        let (fc, fc_len) = receiver.flow_control_frame(FlowStatus::ContinueToSend);
+        assert_eq!(&fc[..fc_len], &[0x30, 2, 0xf5]);
+        receiver.flow_control_sent(FlowStatus::ContinueToSend, 0);
+        sender.on_frame(&info(rx_id, fc_len), &fc, 1000).unwrap();
+        assert!(matches!(
+            sender.tx_state,
+            TxState::Consecutive {
+                next: 1000,
+                block: 2,
+                separation: 500
+            }
+        ));
+
+        // Two consecutive frames 500 µs apart, then wait for the next flow control frame
+        for (now, expect_fc) in [(1000, false), (1500, true)] {
+            let (frame, len, count) = sender.consecutive_frame();
+            sender.frame_sent(count, now);
+            receiver.on_frame(&info(tx_id, len), &frame, now).unwrap();
+            assert_eq!(
+                matches!(receiver.rx_state, RxState::FlowControl(_)),
+                expect_fc
+            );
+        }
+        assert!(matches!(
+            sender.tx_state,
+            TxState::WaitFlowControl {
+                deadline: 1_001_500
+            }
+        ));
+
+        // A block size of 0 does not wait for flow control frames
+        sender.tx_state = TxState::Consecutive {
+            next: 0,
+            block: 0,
+            separation: 0,
+        };
+        sender.frame_sent(7, 0);
+        assert!(matches!(
+            sender.tx_state,
+            TxState::Consecutive { block: 0, .. }
+        ));
+
+        assert_eq!(separation_time(0x00), 0);
+        assert_eq!(separation_time(0x7f), 127_000);
+        assert_eq!(separation_time(0xf1), 100);
+        assert_eq!(separation_time(0xf9), 900);
+        assert_eq!(separation_time(0x80), 127_000);
+        assert_eq!(separation_time(0xfa), 127_000);
+    }
+
+    #[test]
+    fn sequence_number_wraps() {
+        let (tx_id, rx_id) = ids();
+        let mut sender: IsoTp<256> = IsoTp::new(config());
+        let mut receiver: IsoTp<256> = IsoTp::new(IsoTpConfig::new(rx_id, tx_id));
+        // 6 bytes in the first frame, 7 in each of 20 consecutive frames
+        let data = &message()[..146];
+        assert_eq!(transfer(&mut sender, &mut receiver, data), 21);
+        assert_eq!(sender.tx_sn, 21 & 0xf);
+        assert_eq!(receiver.rx_sn, 21 & 0xf);
+
+        // Consecutive frames 15, 0 and 1 are accepted
+        sender.send(data).unwrap();
+        let (frame, len, count) = sender.start_frame();
+        sender.frame_sent(count, 0);
+        receiver.on_frame(&info(tx_id, len), &frame, 0).unwrap();
+        receiver.flow_control_sent(FlowStatus::ContinueToSend, 0);
+        sender.tx_state = TxState::Consecutive {
+            next: 0,
+            block: 0,
+            separation: 0,
+        };
+        for sn in (1..16).chain(0..2) {
+            let (frame, len, count) = sender.consecutive_frame();
+            assert_eq!(frame[0], 0x20 | sn);
+            sender.frame_sent(count, 0);
+            receiver.on_frame(&info(tx_id, len), &frame, 0).unwrap();
+        }
+
+        // Skipping a consecutive frame aborts the reception
+        sender.frame_sent(7, 0);
+        let (frame, len, _) = sender.consecutive_frame();
+        assert_eq!(
+            receiver.on_frame(&info(tx_id, len), &frame, 0),
+            Err(IsoTpError::WrongSequenceNumber)
+        );
+        assert!(matches!(receiver.rx_state, RxState::Idle));
+    }
+
+    #[test]
+    fn expired_across_wraparound() {
+        let now = u32::MAX - 5;
+        let deadline = now.wrapping_add(10);
+        assert_eq!(deadline, 4);
+        assert!(!expired(now, deadline));
+        assert!(!expired(u32::MAX, deadline));
+        assert!(!expired(3, deadline));
+        assert!(expired(4, deadline));
+        assert!(expired(5, deadline));
+        assert!(expired(now, now));
+        assert!(!expired(now, now.wrapping_add(1)));
+
+        let mut since = None;
+        assert!(!blocked(&mut since, now, 10));
+        assert!(!blocked(&mut since, 4, 10));
+        assert!(blocked(&mut since, 5, 10));
+    }
+}
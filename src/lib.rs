@@ -14,7 +14,9 @@
 //!
 //! | Feature | Description |
 //! |---------|-------------|
-//! | `embedded-can-03` | ~Implements the [`embedded-can`] 0.3 traits.~ |
+//! | `embedded-can-03` | Implements the [`embedded-can`] 0.3 traits. |
+//! | `embedded-can-04` | Implements the [`embedded-can`] 0.4 traits. |
+//! | `async` | Async transmit and receive, woken from the FdCan interrupts. |
 //!
 //! [`embedded-can`]: https://docs.rs/embedded-can
 
@@ -26,10 +28,14 @@ mod pac;
 use self::pac::generic::*; // To make the PAC extraction build
 pub use crate::pac::fdcan::RegisterBlock;
 
+/// Async transmit and receive
+#[cfg(feature = "async")]
+pub mod asynch;
+pub mod buffered;
 /// Configuration of an FDCAN instance
 pub mod config;
-// #[cfg(feature = "embedded-can-03")] REVERT ME: embedded-can support
-// mod embedded_can;
+#[cfg(any(feature = "embedded-can-03", feature = "embedded-can-04"))]
+mod embedded_can;
 /// Filtering of CAN Messages
 pub mod filter;
 /// Header and info of transmitted and receiving frames
@@ -38,25 +44,36 @@ pub mod frame;
 pub mod id;
 /// Interrupt Line Information
 pub mod interrupt;
+#[cfg(feature = "isotp")]
+pub mod isotp;
 /// Message RAM block
 pub mod message_ram;
+#[cfg(feature = "fdcan_h7")]
+pub mod tt;
 
 mod sealed {
     pub trait Sealed {}
 }
 
+#[cfg(feature = "fdcan_h7")]
+use config::MessageRamLayout;
 use config::{
-    DataBitTiming, FdCanConfig, FrameTransmissionConfig, GlobalFilter,
-    NominalBitTiming, TimestampSource,
+    BusOffRecovery, DataBitTiming, FdCanConfig, FifoMode, FrameTransmissionConfig,
+    GlobalFilter, NominalBitTiming, TimeoutConfig, TimestampSource,
+    TransmitterDelayCompensation, TxBufferMode,
 };
 use filter::{
-    ActivateFilter as _, ExtendedFilter, ExtendedFilterSlot, StandardFilter,
-    StandardFilterSlot, EXTENDED_FILTER_MAX, STANDARD_FILTER_MAX,
+    ActivateFilter as _, ExtendedFilter, ExtendedFilterSlot, FilterId, StandardFilter,
+    StandardFilterSlot,
 };
+#[cfg(feature = "fdcan_g0_g4_l5")]
+use filter::{EXTENDED_FILTER_MAX, STANDARD_FILTER_MAX};
 use frame::MergeTxFrameHeader;
-use frame::{RxFrameInfo, TxFrameHeader};
+use frame::{Frame, RxFrameInfo, TxEvent, TxFrameHeader};
 use id::{Id, IdReg};
 use interrupt::{Interrupt, InterruptLine, Interrupts};
+#[cfg(feature = "fdcan_h7")]
+use message_ram::MessageRamError;
 
 use message_ram::RxFifoElement;
 
@@ -194,6 +211,148 @@ pub struct ProtocolStatus {
     pub last_error: LastErrorCode,
 }
 
+/// Bus_Off recovery events, reported by [`FdCan::poll_recovery`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BusOffEvent {
+    /// The FdCan entered Bus_Off
+    BusOff,
+    /// A recovery attempt was started. Holds the number of attempts so far, see
+    /// [`FdCanControl::recovery_attempts`]
+    RecoveryStarted(u8),
+    /// The recovery is in progress. Holds the number of sequences of 11 recessive bits
+    /// monitored so far, the recovery finishes after 128 sequences.
+    Recovering(u8),
+    /// The recovery finished and the FdCan is Error Active again
+    Recovered,
+    /// The maximum number of attempts of [`BusOffRecovery::BackOff`] was reached. Only
+    /// [`FdCanControl::start_recovery`] and [`FdCanControl::reset_recovery_attempts`] start
+    /// further attempts.
+    GaveUp,
+}
+
+/// Progress of the Bus_Off recovery
+#[derive(Clone, Copy, Debug)]
+enum RecoveryState {
+    BusOn,
+    /// Waiting for the number of polls before the next attempt
+    BusOff(u32),
+    /// Number of sequences of 11 recessive bits monitored so far
+    Recovering(u8),
+    GaveUp,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Recovery {
+    state: RecoveryState,
+    attempts: u8,
+}
+
+impl Recovery {
+    const fn new() -> Self {
+        Self {
+            state: RecoveryState::BusOn,
+            attempts: 0,
+        }
+    }
+}
+
+/// Error state and error codes, combining the live status with the cached fields of the
+/// protocol status and error counter registers, see [`FdCanControl::error_status`]
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct ErrorStatus {
+    /// Bus_Off status
+    pub bus_off: bool,
+    /// Shows if an error counter reached the warning limit of 96
+    pub error_warning: bool,
+    /// Shows if the node is Error Passive
+    pub error_passive: bool,
+    /// Last error in the arbitration phase, or in a frame without bit rate switching
+    pub last_error: LastErrorCode,
+    /// Last error in the data phase of a FDCAN frame with bit rate switching
+    pub last_data_error: LastErrorCode,
+    /// ESI flag of the last received FDCAN frame
+    pub received_esi: bool,
+    /// BRS flag of the last received FDCAN frame
+    pub received_brs: bool,
+    /// A FDCAN frame was received
+    pub received_fdcan: bool,
+    /// A protocol exception event occurred
+    pub protocol_exception: bool,
+    /// Number of errors logged by the CAN error logging counter
+    pub logged_errors: u16,
+    /// Transmit CAN error counter
+    pub transmit_err: u8,
+    /// Receive CAN error counter
+    pub receive_err: ReceiveErrorOverflow,
+}
+
+/// Error events, decoded by [`FdCanControl::error_event`]
+#[derive(Clone, Copy, Debug)]
+pub enum ErrorEvent {
+    /// The Bus_Off status changed, holds the new status
+    BusOff(bool),
+    /// The Error Passive status changed, holds the new status
+    ErrorPassive(bool),
+    /// The Error Warning status changed, holds the new status
+    ErrorWarning(bool),
+    /// Protocol error in the arbitration phase, or in a frame without bit rate switching
+    Arbitration(LastErrorCode),
+    /// Protocol error in the data phase of a FDCAN frame with bit rate switching
+    Data(LastErrorCode),
+    /// The CAN error logging counter overflowed
+    ErrorLoggingOverflow,
+}
+
+/// Where a high priority message was stored, see [`HighPriorityStatus`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HighPriorityStorage {
+    /// The matching filter does not store the message
+    NotStored,
+    /// The message was lost because the FIFO was full
+    Lost,
+    /// The message is stored in FIFO 0 at the given element index
+    Fifo0(u8),
+    /// The message is stored in FIFO 1 at the given element index
+    Fifo1(u8),
+}
+
+/// Status of the last high priority message, flagged by a filter with
+/// [`Action::FlagHighPrio`](filter::Action::FlagHighPrio) or one of its variants
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HighPriorityStatus {
+    /// Where the message was stored
+    pub storage: HighPriorityStorage,
+    /// The filter that matched the message
+    pub filter: FilterId,
+}
+
+/// The fields of PSR and ECR that are reset when the registers are read
+#[derive(Clone, Copy, Debug)]
+struct StickyErrors {
+    lec: u8,
+    dlec: u8,
+    resi: bool,
+    rbrs: bool,
+    rfdf: bool,
+    pxe: bool,
+    cel: u16,
+}
+
+impl StickyErrors {
+    const fn new() -> Self {
+        Self {
+            lec: LastErrorCode::NoChange as u8,
+            dlec: LastErrorCode::NoChange as u8,
+            resi: false,
+            rbrs: false,
+            rfdf: false,
+            pxe: false,
+            cel: 0,
+        }
+    }
+}
+
 /// Allows for Transmit Operations
 pub trait Transmit {}
 /// Allows for Receive Operations
@@ -228,7 +387,17 @@ impl Receive for NormalOperationMode {}
 /// frames, or overload frames. In case of an error condition or overload condition, it does not
 /// send dominant bits, instead it waits for the occurrence of bus idle condition to resynchronize
 /// itself to the CAN communication. The error counters for transmit and receive are frozen while
-/// error logging (can_errors) is active. TODO: automatically enter in this mode?
+/// error logging (can_errors) is active.
+///
+/// The FdCan also switches to Restricted operation mode by itself when the Tx handler could not
+/// read a frame from the Message RAM in time, flagging [`Interrupt::MsgRamAccessFailure`]. A
+/// stalled Message RAM access is detected by the RAM watchdog, see
+/// [`FdCanConfig::set_ram_watchdog`]. To recover in NormalOperationMode:
+///
+/// 1. [`FdCan::check_restricted_operation`] returns the FdCan as RestrictedOperationMode.
+/// 2. Handle the cause of the failure, e.g. the bus master blocking the Message RAM.
+/// 3. Return to NormalOperationMode with [`FdCan::into_normal`], or reconfigure the FdCan via
+///    [`FdCan::into_config_mode`].
 pub struct RestrictedOperationMode;
 impl Receive for RestrictedOperationMode {}
 ///  In Bus monitoring mode (for more details refer to ISO11898-1, 10.12 Bus monitoring),
@@ -260,6 +429,8 @@ where
             control: FdCanControl {
                 config,
                 instance,
+                recovery: Recovery::new(),
+                sticky_errors: StickyErrors::new(),
                 _mode: core::marker::PhantomData,
             },
         }
@@ -270,6 +441,8 @@ where
             control: FdCanControl {
                 config: self.control.config,
                 instance: self.control.instance,
+                recovery: self.control.recovery,
+                sticky_errors: self.control.sticky_errors,
                 _mode: core::marker::PhantomData,
             },
         }
@@ -289,13 +462,121 @@ where
     }
 
     #[inline]
-    fn msg_ram_mut(&mut self) -> &mut message_ram::RegisterBlock {
-        self.instance().msg_ram_mut()
+    fn reset_msg_ram(&mut self) {
+        #[cfg(feature = "fdcan_g0_g4_l5")]
+        self.instance().msg_ram_mut().reset();
+        #[cfg(feature = "fdcan_h7")]
+        message_ram::reset::<I>(&self.control.config.message_ram_layout);
+    }
+
+    /// Writes the start addresses and sizes of the sections in `layout` into the Message RAM
+    /// configuration registers
+    #[cfg(feature = "fdcan_h7")]
+    fn write_message_ram_layout(&mut self, layout: MessageRamLayout) {
+        let can = self.registers();
+
+        can.sidfc.write(|w| unsafe {
+            w.flssa()
+                .bits(layout.standard_filter_start())
+                .lss()
+                .bits(layout.standard_filters)
+        });
+        can.xidfc.write(|w| unsafe {
+            w.flesa()
+                .bits(layout.extended_filter_start())
+                .lse()
+                .bits(layout.extended_filters)
+        });
+        can.rxf0c.modify(|_, w| unsafe {
+            w.f0sa()
+                .bits(layout.rx_fifo0_start())
+                .f0s()
+                .bits(layout.rx_fifo0_size)
+        });
+        can.rxf1c.modify(|_, w| unsafe {
+            w.f1sa()
+                .bits(layout.rx_fifo1_start())
+                .f1s()
+                .bits(layout.rx_fifo1_size)
+        });
+        can.rxbc
+            .write(|w| unsafe { w.rbsa().bits(layout.rx_buffer_start()) });
+        can.txefc.modify(|_, w| unsafe {
+            w.efsa()
+                .bits(layout.tx_event_start())
+                .efs()
+                .bits(layout.tx_events)
+        });
+        can.txbc.modify(|_, w| unsafe {
+            w.tbsa()
+                .bits(layout.tx_buffer_start())
+                .ndtb()
+                .bits(layout.dedicated_tx_buffers)
+                .tfqs()
+                .bits(layout.tx_buffers)
+        });
+        can.rxesc.write(|w| unsafe {
+            w.f0ds()
+                .bits(layout.rx_fifo0_data_size as u8)
+                .f1ds()
+                .bits(layout.rx_fifo1_data_size as u8)
+                .rbds()
+                .bits(layout.rx_buffer_data_size as u8)
+        });
+        can.txesc
+            .write(|w| unsafe { w.tbds().bits(layout.tx_buffer_data_size as u8) });
+    }
+
+    /// Disables all standard and extended filters of the Message RAM layout
+    fn disable_filters(&mut self) {
+        for fid in 0..message_ram::standard_filters::<I>() {
+            self.set_standard_filter(fid.into(), StandardFilter::disable());
+        }
+        for fid in 0..message_ram::extended_filters::<I>() {
+            self.set_extended_filter(fid.into(), ExtendedFilter::disable());
+        }
     }
 
     #[inline]
-    fn reset_msg_ram(&mut self) {
-        self.msg_ram_mut().reset();
+    fn standard_filter(&self, idx: u8) -> &message_ram::StandardFilter {
+        assert!(
+            idx < message_ram::standard_filters::<I>(),
+            "Standard filter slot is not in the Message RAM layout"
+        );
+        // Safety: The element is within our Message RAM region, and only written through
+        // `&mut self`.
+        unsafe { message_ram::standard_filter::<I>(idx) }
+    }
+
+    #[inline]
+    fn extended_filter(&self, idx: u8) -> &message_ram::ExtendedFilter {
+        assert!(
+            idx < message_ram::extended_filters::<I>(),
+            "Extended filter slot is not in the Message RAM layout"
+        );
+        // Safety: The element is within our Message RAM region, and only written through
+        // `&mut self`.
+        unsafe { message_ram::extended_filter::<I>(idx) }
+    }
+
+    #[inline]
+    fn standard_filter_mut(&mut self, idx: u8) -> &mut message_ram::StandardFilter {
+        assert!(
+            idx < message_ram::standard_filters::<I>(),
+            "Standard filter slot is not in the Message RAM layout"
+        );
+        // Safety: We have a `&mut self` and the element is within our Message RAM region.
+        unsafe { message_ram::standard_filter::<I>(idx) }
+    }
+
+    #[inline]
+    fn extended_filter_mut(&mut self, idx: u8) -> &mut message_ram::ExtendedFilter {
+        assert!(
+            idx < message_ram::extended_filters::<I>(),
+            "Extended filter slot is not in the Message RAM layout"
+        );
+        // Safety: We have a `&mut self` and the element is within our Message RAM region.
+        unsafe { message_ram::extended_filter::<I>(idx) }
     }
 
     #[inline]
@@ -378,10 +659,17 @@ where
     }
 
     /// Starts listening for a set of CAN interrupts.
+    ///
+    /// Enabling [`Interrupt::TxComplete`] also enables the transmission interrupt of every
+    /// configured Tx buffer (TXBTIE), as TxComplete is only raised for those buffers.
     #[inline]
     pub fn enable_interrupts(&mut self, interrupts: Interrupts) {
-        self.registers()
-            .ie
+        let can = self.registers();
+        if interrupts.contains(Interrupts::TX_COMPLETE) {
+            can.txbtie
+                .write(|w| unsafe { w.tie().bits(message_ram::tx_buffer_mask::<I>()) });
+        }
+        can.ie
             .modify(|r, w| unsafe { w.bits(r.bits() | interrupts.bits()) })
     }
 
@@ -393,66 +681,219 @@ where
     /// Stops listening for a set of CAN interrupts.
     #[inline]
     pub fn disable_interrupts(&mut self, interrupts: Interrupts) {
-        self.registers()
-            .ie
-            .modify(|r, w| unsafe { w.bits(r.bits() & !interrupts.bits()) })
+        let can = self.registers();
+        can.ie
+            .modify(|r, w| unsafe { w.bits(r.bits() & !interrupts.bits()) });
+        if interrupts.contains(Interrupts::TX_COMPLETE) {
+            can.txbtie.write(|w| unsafe { w.tie().bits(0) });
+        }
     }
 
-    /// Retrieve the CAN error counters
+    /// Retrieve the CAN error counters, see [`FdCanControl::error_counters`]
     #[inline]
-    pub fn error_counters(&self) -> ErrorCounters {
+    pub fn error_counters(&mut self) -> ErrorCounters {
         self.control.error_counters()
     }
 
+    /// Returns the error state and error codes, see [`FdCanControl::error_status`]
+    #[inline]
+    pub fn error_status(&mut self) -> ErrorStatus {
+        self.control.error_status()
+    }
+
+    /// Resets the cached error codes, see [`FdCanControl::clear_error_status`]
+    #[inline]
+    pub fn clear_error_status(&mut self) {
+        self.control.clear_error_status()
+    }
+
+    /// Decodes the next pending error interrupt, see [`FdCanControl::error_event`]
+    #[inline]
+    pub fn error_event(&mut self) -> Option<ErrorEvent> {
+        self.control.error_event()
+    }
+
+    /// Returns the status of the last high priority message, see
+    /// [`FdCanControl::high_priority_status`]
+    #[inline]
+    pub fn high_priority_status(&self) -> HighPriorityStatus {
+        self.control.high_priority_status()
+    }
+
+    /// Returns the current value of the timeout counter
+    #[inline]
+    pub fn timeout_counter(&self) -> u16 {
+        self.control.timeout_counter()
+    }
+
+    /// Returns the current value of the Message RAM watchdog
+    #[inline]
+    pub fn ram_watchdog_value(&self) -> u8 {
+        self.control.ram_watchdog_value()
+    }
+
+    /// Returns `true` if the FdCan is in Restricted Operation Mode. Besides
+    /// [`FdCan::into_restricted`], this mode is entered automatically on a Message RAM access
+    /// failure, see [`RestrictedOperationMode`].
+    #[inline]
+    pub fn is_restricted_operation(&self) -> bool {
+        self.registers().cccr.read().asm().bit_is_set()
+    }
+
+    /// Restarts the timeout counter, see [`FdCanControl::reset_timeout`]
+    #[inline]
+    pub fn reset_timeout(&mut self) {
+        self.control.reset_timeout()
+    }
+
     /// Set an Standard Address CAN filter into slot 'id'
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slot` is not within the standard filters of the Message RAM layout, or if the
+    /// filter stores in a dedicated Rx buffer that is not in the Message RAM layout.
     #[inline]
     pub fn set_standard_filter(
         &mut self,
         slot: StandardFilterSlot,
         filter: StandardFilter,
     ) {
-        self.msg_ram_mut().filters.flssa[slot as usize].activate(filter);
+        #[cfg(feature = "fdcan_h7")]
+        self.check_rx_buffer(filter.action);
+        self.standard_filter_mut(slot as u8).activate(filter);
     }
 
-    /// Set an array of Standard Address CAN filters and overwrite the current set
-    pub fn set_standard_filters(
-        &mut self,
-        filters: &[StandardFilter; STANDARD_FILTER_MAX as usize],
-    ) {
-        for (i, f) in filters.iter().enumerate() {
-            self.msg_ram_mut().filters.flssa[i].activate(*f);
+    /// Set a list of Standard Address CAN filters and overwrite the current set. The slots
+    /// after the given filters are disabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more filters than standard filters in the Message RAM layout, or if a
+    /// filter stores in a dedicated Rx buffer that is not in the Message RAM layout.
+    pub fn set_standard_filters(&mut self, filters: &[StandardFilter]) {
+        let slots = message_ram::standard_filters::<I>();
+        assert!(
+            filters.len() <= slots as usize,
+            "More standard filters than slots in the Message RAM layout"
+        );
+        #[cfg(feature = "fdcan_h7")]
+        for filter in filters {
+            self.check_rx_buffer(filter.action);
+        }
+        for idx in 0..slots {
+            let filter = filters
+                .get(idx as usize)
+                .copied()
+                .unwrap_or_else(StandardFilter::disable);
+            self.standard_filter_mut(idx).activate(filter);
         }
     }
 
     /// Set an Extended Address CAN filter into slot 'id'
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slot` is not within the extended filters of the Message RAM layout, or if the
+    /// filter stores in a dedicated Rx buffer that is not in the Message RAM layout.
     #[inline]
     pub fn set_extended_filter(
         &mut self,
         slot: ExtendedFilterSlot,
         filter: ExtendedFilter,
     ) {
-        self.msg_ram_mut().filters.flesa[slot as usize].activate(filter);
+        #[cfg(feature = "fdcan_h7")]
+        self.check_rx_buffer(filter.action);
+        self.extended_filter_mut(slot as u8).activate(filter);
     }
 
-    /// Set an array of Extended Address CAN filters and overwrite the current set
-    pub fn set_extended_filters(
-        &mut self,
-        filters: &[ExtendedFilter; EXTENDED_FILTER_MAX as usize],
-    ) {
-        for (i, f) in filters.iter().enumerate() {
-            self.msg_ram_mut().filters.flesa[i].activate(*f);
+    /// Set a list of Extended Address CAN filters and overwrite the current set. The slots
+    /// after the given filters are disabled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are more filters than extended filters in the Message RAM layout, or if a
+    /// filter stores in a dedicated Rx buffer that is not in the Message RAM layout.
+    pub fn set_extended_filters(&mut self, filters: &[ExtendedFilter]) {
+        let slots = message_ram::extended_filters::<I>();
+        assert!(
+            filters.len() <= slots as usize,
+            "More extended filters than slots in the Message RAM layout"
+        );
+        #[cfg(feature = "fdcan_h7")]
+        for filter in filters {
+            self.check_rx_buffer(filter.action);
+        }
+        for idx in 0..slots {
+            let filter = filters
+                .get(idx as usize)
+                .copied()
+                .unwrap_or_else(ExtendedFilter::disable);
+            self.extended_filter_mut(idx).activate(filter);
+        }
+    }
+
+    /// Checks that a filter storing in a dedicated Rx buffer uses a buffer of the layout, as the
+    /// filter element only holds the buffer index.
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    fn check_rx_buffer(&self, action: filter::Action) {
+        if let filter::Action::StoreInRxBuffer(idx) = action {
+            assert!(
+                idx < self.control.config.message_ram_layout.rx_buffers,
+                "Rx buffer is not in the Message RAM layout"
+            );
         }
     }
 
+    /// Reads back the Standard Address CAN filter in slot 'id'
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slot` is not within the standard filters of the Message RAM layout.
+    #[inline]
+    pub fn get_standard_filter(&self, slot: StandardFilterSlot) -> StandardFilter {
+        self.standard_filter(slot as u8).read_filter()
+    }
+
+    /// Reads back the Extended Address CAN filter in slot 'id'
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slot` is not within the extended filters of the Message RAM layout.
+    #[inline]
+    pub fn get_extended_filter(&self, slot: ExtendedFilterSlot) -> ExtendedFilter {
+        self.extended_filter(slot as u8).read_filter()
+    }
+
+    /// Returns an iterator over all Standard Address CAN filters of the Message RAM layout
+    pub fn standard_filters(
+        &self,
+    ) -> impl Iterator<Item = (StandardFilterSlot, StandardFilter)> + '_ {
+        (0..message_ram::standard_filters::<I>())
+            .map(move |idx| (idx.into(), self.standard_filter(idx).read_filter()))
+    }
+
+    /// Returns an iterator over all Extended Address CAN filters of the Message RAM layout
+    pub fn extended_filters(
+        &self,
+    ) -> impl Iterator<Item = (ExtendedFilterSlot, ExtendedFilter)> + '_ {
+        (0..message_ram::extended_filters::<I>())
+            .map(move |idx| (idx.into(), self.extended_filter(idx).read_filter()))
+    }
+
     /// Retrieve the current protocol status
-    pub fn get_protocol_status(&self) -> ProtocolStatus {
-        let psr = self.registers().psr.read();
-        let lec = if psr.redl().bit_is_set() && psr.rbrs().bit_is_set() {
+    ///
+    /// The last error code is taken from the cache of [`FdCan::error_status`], so reading the
+    /// protocol status does not reset it.
+    pub fn get_protocol_status(&mut self) -> ProtocolStatus {
+        let psr = self.control.read_psr();
+        let sticky = self.control.sticky_errors;
+        let lec = if sticky.rfdf && sticky.rbrs {
             // Last error from data phase of a FDCAN format frame with its BRS
             // flag set
-            psr.dlec().bits()
+            sticky.dlec
         } else {
-            psr.lec().bits()
+            sticky.lec
         };
         ProtocolStatus {
             activity: Activity::try_from(0 /*psr.act().bits()*/).unwrap(), //TODO: stm32g4 does not allow reading from this register
@@ -464,6 +905,13 @@ where
         }
     }
 
+    /// Returns the transmitter delay measured during the last transmitted FD frame with bit
+    /// rate switching, in minimum time quanta
+    #[inline]
+    pub fn transmitter_delay_compensation_value(&mut self) -> u8 {
+        self.control.read_psr().tdcv().bits()
+    }
+
     /// Check if the interrupt is triggered
     #[inline]
     pub fn has_interrupt(&mut self, interrupt: Interrupt) -> bool {
@@ -539,9 +987,62 @@ where
         Self::create_can(FdCanConfig::default(), instance)
     }
 
+    /// Sets the Message RAM layout that is applied when moving into ConfigMode.
+    /// See [`FdCanConfig::set_message_ram_layout`]
+    ///
+    /// The region of the layout is only claimed when moving into ConfigMode. Returns an error,
+    /// and leaves the current layout in place, if the region overlaps the region currently
+    /// claimed by another instance.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout is invalid, see [`MessageRamLayout::check`].
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    pub fn set_message_ram_layout(
+        &mut self,
+        layout: MessageRamLayout,
+    ) -> Result<(), MessageRamError> {
+        message_ram::check(I::REGISTERS as usize, &layout.check())?;
+        self.control.config.message_ram_layout = layout;
+        Ok(())
+    }
+
+    /// Moves out of PoweredDownMode and into ConfigMode
+    ///
+    /// Returns the error and the FdCan, still in PoweredDownMode, if the Message RAM region of
+    /// this instance overlaps the region of another instance.
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    #[allow(clippy::result_large_err)]
+    pub fn try_into_config_mode(
+        self,
+    ) -> Result<FdCan<I, ConfigMode>, (MessageRamError, Self)> {
+        match message_ram::claim(
+            I::REGISTERS as usize,
+            &self.control.config.message_ram_layout,
+        ) {
+            Ok(()) => Ok(self.into_config_mode()),
+            Err(e) => Err((e, self)),
+        }
+    }
+
     /// Moves out of PoweredDownMode and into ConfigMode
+    ///
+    /// # Panics
+    ///
+    /// On H7, panics if the Message RAM region of this instance overlaps the region of another
+    /// instance. Use `set_message_ram_layout` to place the instances before moving into
+    /// ConfigMode, or `try_into_config_mode` to handle the error.
     #[inline]
     pub fn into_config_mode(mut self) -> FdCan<I, ConfigMode> {
+        #[cfg(feature = "fdcan_h7")]
+        message_ram::claim(
+            I::REGISTERS as usize,
+            &self.control.config.message_ram_layout,
+        )
+        .expect("Message RAM region is used by another FDCAN instance");
+
         self.set_power_down_mode(false);
         self.enter_init_mode();
 
@@ -561,8 +1062,9 @@ where
 
         // Framework specific settings are set here
 
-        // set TxBuffer to Queue Mode
-        can.txbc.write(|w| w.tfqm().set_bit());
+        // set TxBuffer to Queue or FIFO Mode
+        let tx_queue_mode = self.control.config.tx_buffer_mode == TxBufferMode::Queue;
+        can.txbc.write(|w| w.tfqm().bit(tx_queue_mode));
 
         // set standard filters list size to 28
         // set extended filters list size to 8
@@ -577,25 +1079,18 @@ where
                     .bits(EXTENDED_FILTER_MAX)
             });
         }
+        // The Message RAM on H7 is laid out from the configured layout
         #[cfg(feature = "fdcan_h7")]
-        {
-            can.sidfc
-                .modify(|_, w| unsafe { w.lss().bits(STANDARD_FILTER_MAX) });
-            can.xidfc
-                .modify(|_, w| unsafe { w.lse().bits(EXTENDED_FILTER_MAX) });
-        }
+        self.write_message_ram_layout(self.control.config.message_ram_layout);
 
-        for fid in 0..STANDARD_FILTER_MAX {
-            self.set_standard_filter((fid as u8).into(), StandardFilter::disable());
-        }
-        for fid in 0..EXTENDED_FILTER_MAX {
-            self.set_extended_filter(fid.into(), ExtendedFilter::disable());
-        }
+        self.disable_filters();
 
         self.into_can_mode()
     }
 
     /// Disables the CAN interface and returns back the raw peripheral it was created from.
+    ///
+    /// On H7 this releases the Message RAM region of this instance.
     #[inline]
     pub fn free(mut self) -> I {
         self.disable_interrupts(Interrupts::all());
@@ -603,6 +1098,10 @@ where
         //TODO check this!
         self.enter_init_mode();
         self.set_power_down_mode(true);
+
+        #[cfg(feature = "fdcan_h7")]
+        message_ram::release(I::REGISTERS as usize);
+
         self.control.instance
     }
 }
@@ -618,7 +1117,6 @@ where
         let can = self.registers();
         can.cccr.modify(|_, w| w.cce().clear_bit());
         can.cccr.modify(|_, w| w.init().clear_bit());
-        while can.cccr.read().init().bit_is_set() {}
     }
 
     /// Moves out of ConfigMode and into InternalLoopbackMode
@@ -685,8 +1183,43 @@ where
     }
 
     /// Applies the settings of a new FdCanConfig See [`FdCanConfig`]
+    ///
+    /// # Panics
+    ///
+    /// On H7, panics if the Message RAM layout changed and overlaps the region of another
+    /// instance. Use `try_apply_config` to handle the error.
     #[inline]
     pub fn apply_config(&mut self, config: FdCanConfig) {
+        // Changing the layout clears the filters, so only do so when it changed
+        #[cfg(feature = "fdcan_h7")]
+        if config.message_ram_layout != self.control.config.message_ram_layout {
+            self.set_message_ram_layout(config.message_ram_layout)
+                .expect("Message RAM region is used by another FDCAN instance");
+        }
+        self.apply_settings(config);
+    }
+
+    /// Applies the settings of a new FdCanConfig See [`FdCanConfig`]
+    ///
+    /// Returns an error, and leaves the configuration unchanged, if the Message RAM layout
+    /// changed and overlaps the region of another instance.
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    pub fn try_apply_config(
+        &mut self,
+        config: FdCanConfig,
+    ) -> Result<(), MessageRamError> {
+        if config.message_ram_layout != self.control.config.message_ram_layout {
+            self.set_message_ram_layout(config.message_ram_layout)?;
+        }
+        self.apply_settings(config);
+        Ok(())
+    }
+
+    /// Applies all settings of `config` except for the Message RAM layout
+    fn apply_settings(&mut self, config: FdCanConfig) {
+        self.control.config.transmitter_delay_compensation =
+            config.transmitter_delay_compensation;
         self.set_data_bit_timing(config.dbtr);
         self.set_nominal_bit_timing(config.nbtr);
         self.set_automatic_retransmit(config.automatic_retransmit);
@@ -697,6 +1230,39 @@ where
         self.set_edge_filtering(config.edge_filtering);
         self.set_protocol_exception_handling(config.protocol_exception_handling);
         self.set_global_filter(config.global_filter);
+        self.set_tx_buffer_mode(config.tx_buffer_mode);
+        for fifo in [filter::RxFifo::Fifo0, filter::RxFifo::Fifo1] {
+            self.set_rx_fifo_mode(fifo, config.rx_fifo_mode[fifo as usize]);
+            #[cfg(feature = "fdcan_h7")]
+            self.set_rx_fifo_watermark(fifo, config.rx_fifo_watermark[fifo as usize]);
+        }
+        self.set_timeout(config.timeout);
+        self.set_ram_watchdog(config.ram_watchdog);
+    }
+
+    /// Changes the Message RAM layout. See [`FdCanConfig::set_message_ram_layout`]
+    ///
+    /// This clears the Message RAM region of this instance and disables all filters, so filters
+    /// have to be set after changing the layout.
+    ///
+    /// Returns an error, and leaves the current layout in place, if the new region overlaps the
+    /// region of another instance.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the layout is invalid, see [`MessageRamLayout::check`].
+    #[cfg(feature = "fdcan_h7")]
+    pub fn set_message_ram_layout(
+        &mut self,
+        layout: MessageRamLayout,
+    ) -> Result<(), MessageRamError> {
+        message_ram::claim(I::REGISTERS as usize, &layout.check())?;
+        self.control.config.message_ram_layout = layout;
+
+        self.write_message_ram_layout(layout);
+        self.reset_msg_ram();
+        self.disable_filters();
+        Ok(())
     }
 
     /// Configures the bit timings.
@@ -730,13 +1296,18 @@ where
 
     /// Configures the data bit timings for the FdCan Variable Bitrates.
     /// This is not used when frame_transmit is set to anything other than AllowFdCanAndBRS.
+    ///
+    /// Unless set with [`FdCan::set_transmitter_delay_compensation`], the transmitter
+    /// delay compensation offset follows the sample point of `btr`.
     #[inline]
     pub fn set_data_bit_timing(&mut self, btr: DataBitTiming) {
         self.control.config.dbtr = btr;
 
         let can = self.registers();
         can.dbtp.write(|w| unsafe {
-            w.dbrp()
+            w.tdc()
+                .bit(btr.tdc())
+                .dbrp()
                 .bits(btr.dbrp() - 1)
                 .dtseg1()
                 .bits(btr.dtseg1() - 1)
@@ -745,6 +1316,36 @@ where
                 .dsjw()
                 .bits(btr.dsjw() - 1)
         });
+
+        let tdc = self
+            .control
+            .config
+            .transmitter_delay_compensation
+            .unwrap_or_else(|| {
+                TransmitterDelayCompensation::from_data_bit_timing(&btr)
+            });
+        self.write_transmitter_delay_compensation(tdc);
+    }
+
+    /// Configures the transmitter delay compensation offset and filter window, see
+    /// [`TransmitterDelayCompensation`]
+    #[inline]
+    pub fn set_transmitter_delay_compensation(
+        &mut self,
+        tdc: TransmitterDelayCompensation,
+    ) {
+        self.control.config.transmitter_delay_compensation = Some(tdc);
+        self.write_transmitter_delay_compensation(tdc);
+    }
+
+    #[inline]
+    fn write_transmitter_delay_compensation(
+        &mut self,
+        tdc: TransmitterDelayCompensation,
+    ) {
+        self.registers().tdcr.write(|w| unsafe {
+            w.tdco().bits(tdc.offset).tdcf().bits(tdc.filter_window)
+        });
     }
 
     /// Enables or disables automatic retransmission of messages
@@ -852,38 +1453,123 @@ where
         self.control.config.timestamp_source = select;
     }
 
-    /// Configures the global filter settings
+    /// Configures the timeout counter. See [`TimeoutConfig`]
     #[inline]
-    pub fn set_global_filter(&mut self, filter: GlobalFilter) {
-        self.registers().rxgfc.modify(|_, w| {
-            unsafe {
-                w.anfs()
-                    .bits(filter.handle_standard_frames as u8)
-                    .anfe()
-                    .bits(filter.handle_extended_frames as u8)
-            }
-            .rrfs()
-            .bit(filter.reject_remote_standard_frames)
-            .rrfe()
-            .bit(filter.reject_remote_extended_frames)
-        });
+    pub fn set_timeout(&mut self, timeout: TimeoutConfig) {
+        let (etoc, tos, top) = match timeout {
+            TimeoutConfig::Disabled => (false, 0b00, 0xFFFF),
+            TimeoutConfig::Continuous(top) => (true, 0b00, top),
+            TimeoutConfig::TxEventFifo(top) => (true, 0b01, top),
+            TimeoutConfig::RxFifo0(top) => (true, 0b10, top),
+            TimeoutConfig::RxFifo1(top) => (true, 0b11, top),
+        };
+        self.registers()
+            .tocc
+            .write(|w| unsafe { w.etoc().bit(etoc).tos().bits(tos).top().bits(top) });
+
+        self.control.config.timeout = timeout;
     }
 
-    /// Returns the current FdCan timestamp counter
+    /// Sets the start value of the Message RAM watchdog. See [`FdCanConfig::set_ram_watchdog`]
     #[inline]
-    pub fn timestamp(&self) -> u16 {
-        self.control.timestamp()
+    pub fn set_ram_watchdog(&mut self, start: u8) {
+        // The PAC describes RWD as read-only, but WDC is writable while in ConfigMode. The
+        // upper bits (WDV) are read-only, so writing the whole register is fine.
+        let rwd = self.registers().rwd.as_ptr();
+        unsafe { core::ptr::write_volatile(rwd, u32::from(start)) };
+
+        self.control.config.ram_watchdog = start;
     }
-}
 
-impl<I> FdCan<I, InternalLoopbackMode>
-where
-    I: Instance,
-{
-    /// Returns out of InternalLoopbackMode and back into ConfigMode
+    /// Configures the Tx buffer mode. See [`FdCanConfig::set_tx_buffer_mode`]
     #[inline]
-    pub fn into_config_mode(mut self) -> FdCan<I, ConfigMode> {
-        self.set_loopback_mode(LoopbackMode::None);
+    pub fn set_tx_buffer_mode(&mut self, txbm: TxBufferMode) {
+        self.registers()
+            .txbc
+            .modify(|_, w| w.tfqm().bit(txbm == TxBufferMode::Queue));
+
+        self.control.config.tx_buffer_mode = txbm;
+    }
+
+    /// Configures the operation mode of an Rx FIFO. See [`FdCanConfig::set_rx_fifo_mode`]
+    ///
+    /// In overwrite mode, the FdCan may overwrite the oldest frame while it is read. Reading a
+    /// full FIFO therefore may return a mix of two frames.
+    #[inline]
+    pub fn set_rx_fifo_mode(&mut self, fifo: filter::RxFifo, mode: FifoMode) {
+        let overwrite = mode == FifoMode::Overwrite;
+        let can = self.registers();
+        #[cfg(feature = "fdcan_g0_g4_l5")]
+        can.rxgfc.modify(|_, w| match fifo {
+            filter::RxFifo::Fifo0 => w.f0om().bit(overwrite),
+            filter::RxFifo::Fifo1 => w.f1om().bit(overwrite),
+        });
+        #[cfg(feature = "fdcan_h7")]
+        match fifo {
+            filter::RxFifo::Fifo0 => can.rxf0c.modify(|_, w| w.f0om().bit(overwrite)),
+            filter::RxFifo::Fifo1 => can.rxf1c.modify(|_, w| w.f1om().bit(overwrite)),
+        }
+
+        self.control.config.rx_fifo_mode[fifo as usize] = mode;
+    }
+
+    /// Configures the watermark of an Rx FIFO. See [`FdCanConfig::set_rx_fifo_watermark`]
+    ///
+    /// # Panics
+    ///
+    /// Panics if `watermark` is larger than 64.
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    pub fn set_rx_fifo_watermark(&mut self, fifo: filter::RxFifo, watermark: u8) {
+        assert!(watermark <= 64, "Rx FIFO watermark is larger than 64");
+        let can = self.registers();
+        match fifo {
+            filter::RxFifo::Fifo0 => {
+                can.rxf0c.modify(|_, w| unsafe { w.f0wm().bits(watermark) })
+            }
+            filter::RxFifo::Fifo1 => {
+                can.rxf1c.modify(|_, w| unsafe { w.f1wm().bits(watermark) })
+            }
+        }
+
+        self.control.config.rx_fifo_watermark[fifo as usize] = watermark;
+    }
+
+    /// Configures the global filter settings
+    #[inline]
+    pub fn set_global_filter(&mut self, filter: GlobalFilter) {
+        self.registers().rxgfc.modify(|_, w| {
+            unsafe {
+                w.anfs()
+                    .bits(filter.handle_standard_frames as u8)
+                    .anfe()
+                    .bits(filter.handle_extended_frames as u8)
+            }
+            .rrfs()
+            .bit(filter.reject_remote_standard_frames)
+            .rrfe()
+            .bit(filter.reject_remote_extended_frames)
+        });
+        self.registers()
+            .xidam
+            .write(|w| unsafe { w.eidm().bits(filter.extended_id_mask) });
+    }
+
+    /// Returns the current FdCan timestamp counter
+    #[inline]
+    pub fn timestamp(&self) -> u16 {
+        self.control.timestamp()
+    }
+}
+
+impl<I> FdCan<I, InternalLoopbackMode>
+where
+    I: Instance,
+{
+    /// Returns out of InternalLoopbackMode and back into ConfigMode
+    #[inline]
+    pub fn into_config_mode(mut self) -> FdCan<I, ConfigMode> {
+        self.set_loopback_mode(LoopbackMode::None);
         self.enter_init_mode();
 
         self.into_can_mode()
@@ -916,6 +1602,20 @@ where
 
         self.into_can_mode()
     }
+
+    /// Returns the FdCan as `Err` in RestrictedOperationMode if it switched to Restricted
+    /// operation mode after a Message RAM access failure, see [`RestrictedOperationMode`]
+    #[inline]
+    #[allow(clippy::result_large_err)]
+    pub fn check_restricted_operation(
+        self,
+    ) -> Result<Self, FdCan<I, RestrictedOperationMode>> {
+        if self.is_restricted_operation() {
+            Err(self.into_can_mode())
+        } else {
+            Ok(self)
+        }
+    }
 }
 
 impl<I> FdCan<I, RestrictedOperationMode>
@@ -930,6 +1630,15 @@ where
 
         self.into_can_mode()
     }
+
+    /// Leaves Restricted operation mode and continues in NormalOperationMode, keeping the
+    /// configuration
+    #[inline]
+    pub fn into_normal(mut self) -> FdCan<I, NormalOperationMode> {
+        self.set_restricted_operations(false);
+
+        self.into_can_mode()
+    }
 }
 
 impl<I> FdCan<I, BusMonitoringMode>
@@ -1060,6 +1769,33 @@ where
         unsafe { Tx::<I, M>::conjure().transmit_preserve(frame, buffer, pending) }
     }
 
+    /// Puts a CAN frame in a dedicated Tx buffer, see [`Tx::transmit_dedicated`]
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    pub fn transmit_dedicated(
+        &mut self,
+        idx: Mailbox,
+        frame: TxFrameHeader,
+        buffer: &[u8],
+    ) -> nb::Result<(), Infallible> {
+        // Safety: We have a `&mut self` and have unique access to the peripheral.
+        unsafe { Tx::<I, M>::conjure().transmit_dedicated(idx, frame, buffer) }
+    }
+
+    /// Reserves a Tx buffer to build a frame in place, see [`Tx::reserve`]
+    #[inline]
+    pub fn reserve(&mut self) -> nb::Result<TxSlot<'_, I, M>, Infallible> {
+        // Safety: We have a `&mut self` and have unique access to the peripheral.
+        unsafe { Tx::<I, M>::conjure_by_ref() }.reserve()
+    }
+
+    /// Returns the status of a Tx buffer, see [`Tx::buffer_status`]
+    #[inline]
+    pub fn buffer_status(&self, idx: Mailbox) -> TxBufferStatus {
+        // Safety: Read-only operation.
+        unsafe { Tx::<I, M>::conjure().buffer_status(idx) }
+    }
+
     /// Returns `true` if no frame is pending for transmission.
     #[inline]
     pub fn is_transmitter_idle(&self) -> bool {
@@ -1079,6 +1815,46 @@ where
         // Safety: We have a `&mut self` and have unique access to the peripheral.
         unsafe { Tx::<I, M>::conjure().abort(mailbox) }
     }
+
+    /// Drives the Bus_Off recovery, see [`FdCanControl::poll_recovery`]
+    #[inline]
+    pub fn poll_recovery(&mut self) -> Option<BusOffEvent> {
+        self.control.poll_recovery()
+    }
+
+    /// Starts a Bus_Off recovery attempt, see [`FdCanControl::start_recovery`]
+    #[inline]
+    pub fn start_recovery(&mut self) -> Option<BusOffEvent> {
+        self.control.start_recovery()
+    }
+
+    /// Returns the number of Bus_Off recovery attempts, see
+    /// [`FdCanControl::recovery_attempts`]
+    #[inline]
+    pub fn recovery_attempts(&self) -> u8 {
+        self.control.recovery_attempts()
+    }
+
+    /// Resets the number of Bus_Off recovery attempts, see
+    /// [`FdCanControl::reset_recovery_attempts`]
+    #[inline]
+    pub fn reset_recovery_attempts(&mut self) {
+        self.control.reset_recovery_attempts()
+    }
+
+    /// Returns an iterator that pops all events of the Tx Event FIFO, see [`Tx::tx_events`]
+    #[inline]
+    pub fn tx_events(&mut self) -> TxEvents<'_, I, M> {
+        // Safety: We have a `&mut self` and have unique access to the peripheral.
+        unsafe { Tx::<I, M>::conjure_by_ref() }.tx_events()
+    }
+
+    /// Returns `true` if Tx events were lost, see [`Tx::tx_events_lost`]
+    #[inline]
+    pub fn tx_events_lost(&mut self) -> bool {
+        // Safety: We have a `&mut self` and have unique access to the peripheral.
+        unsafe { Tx::<I, M>::conjure().tx_events_lost() }
+    }
 }
 
 impl<I, M> FdCan<I, M>
@@ -1115,6 +1891,71 @@ where
         // Safety: We have a `&mut self` and have unique access to the peripheral.
         unsafe { Rx::<I, M, Fifo1>::conjure().receive(buffer) }
     }
+
+    /// Returns a received frame from FIFO_0 if available, borrowing its data in the Message
+    /// RAM. See [`Rx::receive_ref`]
+    #[inline]
+    pub fn receive0_ref(
+        &mut self,
+    ) -> nb::Result<ReceiveOverrun<RxFrameRef<'_, I, M, Fifo0>>, Infallible> {
+        // Safety: We have a `&mut self` and have unique access to the peripheral.
+        unsafe { Rx::<I, M, Fifo0>::conjure_by_ref() }.receive_ref()
+    }
+
+    /// Returns a received frame from FIFO_1 if available, borrowing its data in the Message
+    /// RAM. See [`Rx::receive_ref`]
+    #[inline]
+    pub fn receive1_ref(
+        &mut self,
+    ) -> nb::Result<ReceiveOverrun<RxFrameRef<'_, I, M, Fifo1>>, Infallible> {
+        // Safety: We have a `&mut self` and have unique access to the peripheral.
+        unsafe { Rx::<I, M, Fifo1>::conjure_by_ref() }.receive_ref()
+    }
+
+    /// Reads the last high priority message from its FIFO, see
+    /// [`Rx::receive_high_priority`]
+    pub fn receive_high_priority(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> nb::Result<RxFrameInfo, Infallible> {
+        // Safety: We have a `&mut self` and have unique access to the peripheral.
+        match self.high_priority_status().storage {
+            HighPriorityStorage::Fifo0(_) => unsafe {
+                Rx::<I, M, Fifo0>::conjure().receive_high_priority(buffer)
+            },
+            HighPriorityStorage::Fifo1(_) => unsafe {
+                Rx::<I, M, Fifo1>::conjure().receive_high_priority(buffer)
+            },
+            _ => Err(nb::Error::WouldBlock),
+        }
+    }
+
+    /// Returns the dedicated Rx buffers holding new data, see
+    /// [`FdCanControl::rx_buffers_with_new_data`]
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    pub fn rx_buffers_with_new_data(&self) -> u64 {
+        self.control.rx_buffers_with_new_data()
+    }
+
+    /// Returns `true` if the dedicated Rx buffer `idx` holds new data, see
+    /// [`FdCanControl::has_new_rx_buffer_data`]
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    pub fn has_new_rx_buffer_data(&self, idx: u8) -> bool {
+        self.control.has_new_rx_buffer_data(idx)
+    }
+
+    /// Reads the frame in a dedicated Rx buffer, see [`FdCanControl::read_rx_buffer`]
+    #[cfg(feature = "fdcan_h7")]
+    #[inline]
+    pub fn read_rx_buffer(
+        &mut self,
+        idx: u8,
+        buffer: &mut [u8],
+    ) -> nb::Result<RxFrameInfo, Infallible> {
+        self.control.read_rx_buffer(idx, buffer)
+    }
 }
 
 /// FdCanControl Struct
@@ -1126,6 +1967,8 @@ where
 {
     config: FdCanConfig,
     instance: I,
+    recovery: Recovery,
+    sticky_errors: StickyErrors,
     _mode: PhantomData<MODE>,
 }
 impl<I, MODE> FdCanControl<I, MODE>
@@ -1138,16 +1981,18 @@ where
     }
 
     /// Returns the current error counters
+    ///
+    /// `can_errors` is the error logging count cached by [`FdCanControl::error_status`],
+    /// saturated to 255, so reading the counters does not reset it.
     #[inline]
-    pub fn error_counters(&self) -> ErrorCounters {
-        let can = self.registers();
-        let cel: u8 = can.ecr.read().cel().bits();
-        let rp: bool = can.ecr.read().rp().bits();
-        let rec: u8 = can.ecr.read().rec().bits();
-        let tec: u8 = can.ecr.read().tec().bits();
+    pub fn error_counters(&mut self) -> ErrorCounters {
+        let ecr = self.read_ecr();
+        let rp: bool = ecr.rp().bits();
+        let rec: u8 = ecr.rec().bits();
+        let tec: u8 = ecr.tec().bits();
 
         ErrorCounters {
-            can_errors: cel,
+            can_errors: self.sticky_errors.cel.min(u8::MAX.into()) as u8,
             transmit_err: tec,
             receive_err: match rp {
                 false => ReceiveErrorOverflow::Normal(rec),
@@ -1156,12 +2001,148 @@ where
         }
     }
 
+    /// Reads PSR, keeping the fields that are reset on read
+    fn read_psr(&mut self) -> pac::fdcan::psr::R {
+        let psr = self.registers().psr.read();
+        let sticky = &mut self.sticky_errors;
+        if psr.lec().bits() != LastErrorCode::NoChange as u8 {
+            sticky.lec = psr.lec().bits();
+        }
+        if psr.dlec().bits() != LastErrorCode::NoChange as u8 {
+            sticky.dlec = psr.dlec().bits();
+        }
+        if psr.redl().bit_is_set() {
+            sticky.resi = psr.resi().bit_is_set();
+            sticky.rbrs = psr.rbrs().bit_is_set();
+            sticky.rfdf = true;
+        }
+        sticky.pxe |= psr.pxe().bit_is_set();
+        psr
+    }
+
+    /// Reads ECR, accumulating the error logging counter which is reset on read
+    fn read_ecr(&mut self) -> pac::fdcan::ecr::R {
+        let ecr = self.registers().ecr.read();
+        self.sticky_errors.cel = self
+            .sticky_errors
+            .cel
+            .saturating_add(u16::from(ecr.cel().bits()));
+        ecr
+    }
+
+    /// Returns the error state and error codes.
+    ///
+    /// Reading PSR and ECR resets the last error codes, the flags of the last received frame
+    /// and the error logging counter. These are cached, so that this read is not destructive
+    /// for other callers of this function or [`FdCanControl::error_event`]. The cache is reset
+    /// with [`FdCanControl::clear_error_status`].
+    pub fn error_status(&mut self) -> ErrorStatus {
+        let psr = self.read_psr();
+        let ecr = self.read_ecr();
+        let sticky = self.sticky_errors;
+        ErrorStatus {
+            bus_off: psr.bo().bit_is_set(),
+            error_warning: psr.ew().bit_is_set(),
+            error_passive: psr.ep().bit_is_set(),
+            last_error: LastErrorCode::try_from(sticky.lec).unwrap(),
+            last_data_error: LastErrorCode::try_from(sticky.dlec).unwrap(),
+            received_esi: sticky.resi,
+            received_brs: sticky.rbrs,
+            received_fdcan: sticky.rfdf,
+            protocol_exception: sticky.pxe,
+            logged_errors: sticky.cel,
+            transmit_err: ecr.tec().bits(),
+            receive_err: match ecr.rp().bits() {
+                false => ReceiveErrorOverflow::Normal(ecr.rec().bits()),
+                true => ReceiveErrorOverflow::Overflow(ecr.rec().bits()),
+            },
+        }
+    }
+
+    /// Resets the cached error codes, flags and error logging count of
+    /// [`FdCanControl::error_status`]
+    #[inline]
+    pub fn clear_error_status(&mut self) {
+        self.read_psr();
+        self.read_ecr();
+        self.sticky_errors = StickyErrors::new();
+    }
+
+    /// Decodes the next pending error interrupt into an [`ErrorEvent`] and clears its flag.
+    /// Call this repeatedly until it returns `None`.
+    ///
+    /// This does not require the interrupts to be enabled. The error codes are taken from the
+    /// cache of [`FdCanControl::error_status`].
+    pub fn error_event(&mut self) -> Option<ErrorEvent> {
+        let interrupt = [
+            Interrupt::BusOff,
+            Interrupt::ErrPassive,
+            Interrupt::WarningStatus,
+            Interrupt::ProtErrArbritation,
+            Interrupt::ProtErrData,
+            Interrupt::ErrLogOverflow,
+        ]
+        .iter()
+        .copied()
+        .find(|&i| self.has_interrupt(i))?;
+        self.clear_interrupt(interrupt);
+
+        let status = self.error_status();
+        Some(match interrupt {
+            Interrupt::BusOff => ErrorEvent::BusOff(status.bus_off),
+            Interrupt::ErrPassive => ErrorEvent::ErrorPassive(status.error_passive),
+            Interrupt::WarningStatus => ErrorEvent::ErrorWarning(status.error_warning),
+            Interrupt::ProtErrArbritation => ErrorEvent::Arbitration(status.last_error),
+            Interrupt::ProtErrData => ErrorEvent::Data(status.last_data_error),
+            _ => ErrorEvent::ErrorLoggingOverflow,
+        })
+    }
+
     /// Returns the current FdCan Timestamp counter
     #[inline]
     pub fn timestamp(&self) -> u16 {
         self.registers().tscv.read().tsc().bits()
     }
 
+    /// Returns the status of the last high priority message. This is only valid after
+    /// [`Interrupt::RxHighPrio`] was flagged.
+    pub fn high_priority_status(&self) -> HighPriorityStatus {
+        let hpms = self.registers().hpms.read();
+        let idx = hpms.bidx().bits();
+        let fidx = hpms.fidx().bits();
+        HighPriorityStatus {
+            storage: match hpms.msi().bits() {
+                0b00 => HighPriorityStorage::NotStored,
+                0b01 => HighPriorityStorage::Lost,
+                0b10 => HighPriorityStorage::Fifo0(idx),
+                _ => HighPriorityStorage::Fifo1(idx),
+            },
+            filter: match hpms.flst().bit() {
+                false => FilterId::Standard(fidx.into()),
+                true => FilterId::Extended(fidx.into()),
+            },
+        }
+    }
+
+    /// Returns the current value of the timeout counter
+    #[inline]
+    pub fn timeout_counter(&self) -> u16 {
+        self.registers().tocv.read().toc().bits()
+    }
+
+    /// Returns the current value of the Message RAM watchdog
+    #[inline]
+    pub fn ram_watchdog_value(&self) -> u8 {
+        self.registers().rwd.read().wdv().bits()
+    }
+
+    /// Restarts the timeout counter at its period. This only has an effect with
+    /// [`TimeoutConfig::Continuous`], the FIFO controlled modes restart when the FIFO is empty.
+    #[inline]
+    pub fn reset_timeout(&mut self) {
+        self.registers().tocv.write(|w| unsafe { w.bits(0) });
+    }
+
     /// Check if the interrupt is triggered
     #[inline]
     pub fn has_interrupt(&mut self, interrupt: Interrupt) -> bool {
@@ -1184,6 +2165,190 @@ where
     }
 }
 
+impl<I, M> FdCanControl<I, M>
+where
+    I: Instance,
+    M: Transmit,
+{
+    /// Drives the Bus_Off recovery according to the configured [`BusOffRecovery`] policy.
+    /// Call this periodically, e.g. from a timer or on [`Interrupt::BusOff`]. Returns an
+    /// event when the recovery progressed.
+    ///
+    /// On Bus_Off, the FdCan sets `CCCR.INIT` and stays offline until the recovery is
+    /// started. It then waits for 128 sequences of 11 recessive bits before it becomes Error
+    /// Active again.
+    pub fn poll_recovery(&mut self) -> Option<BusOffEvent> {
+        let bus_off = self.read_psr().bo().bit_is_set();
+        let init = self.registers().cccr.read().init().bit_is_set();
+
+        match self.recovery.state {
+            RecoveryState::BusOn if bus_off => {
+                self.recovery.state = RecoveryState::BusOff(self.recovery_delay());
+                Some(BusOffEvent::BusOff)
+            }
+            RecoveryState::BusOn => None,
+            _ if !bus_off => {
+                // The next Bus_Off event starts over with the first attempt
+                self.recovery.state = RecoveryState::BusOn;
+                self.recovery.attempts = 0;
+                Some(BusOffEvent::Recovered)
+            }
+            RecoveryState::BusOff(wait) => match self.config.bus_off_recovery {
+                BusOffRecovery::Manual => None,
+                BusOffRecovery::Automatic => self.start_recovery(),
+                BusOffRecovery::BackOff { max_attempts, .. } => {
+                    if self.recovery.attempts >= max_attempts {
+                        self.recovery.state = RecoveryState::GaveUp;
+                        Some(BusOffEvent::GaveUp)
+                    } else if wait > 0 {
+                        self.recovery.state = RecoveryState::BusOff(wait - 1);
+                        None
+                    } else {
+                        self.start_recovery()
+                    }
+                }
+            },
+            RecoveryState::Recovering(_) if init => {
+                // The recovery finished and the FdCan went Bus_Off again in between polls
+                self.recovery.state = RecoveryState::BusOff(self.recovery_delay());
+                Some(BusOffEvent::BusOff)
+            }
+            RecoveryState::Recovering(sequences) => {
+                // REC counts the sequences of 11 recessive bits during the recovery
+                let rec = self.read_ecr().rec().bits();
+                if rec != sequences {
+                    self.recovery.state = RecoveryState::Recovering(rec);
+                    Some(BusOffEvent::Recovering(rec))
+                } else {
+                    None
+                }
+            }
+            RecoveryState::GaveUp => None,
+        }
+    }
+
+    /// Starts a Bus_Off recovery attempt by clearing `CCCR.INIT`, regardless of the
+    /// configured policy. Returns `None` if the FdCan is not waiting in Bus_Off.
+    ///
+    /// This does not wait for the write to `CCCR.INIT` to be synchronised into the CAN clock
+    /// domain, so it can be called from the BusOff interrupt.
+    pub fn start_recovery(&mut self) -> Option<BusOffEvent> {
+        let bus_off = self.read_psr().bo().bit_is_set();
+        let can = self.registers();
+        if !bus_off || can.cccr.read().init().bit_is_clear() {
+            return None;
+        }
+
+        can.cccr.modify(|_, w| w.init().clear_bit());
+
+        self.recovery.attempts = self.recovery.attempts.saturating_add(1);
+        self.recovery.state = RecoveryState::Recovering(0);
+        Some(BusOffEvent::RecoveryStarted(self.recovery.attempts))
+    }
+
+    /// Returns the number of recovery attempts of the current Bus_Off event. It is reset when
+    /// the FdCan recovers, or when [`FdCanControl::reset_recovery_attempts`] is called.
+    #[inline]
+    pub fn recovery_attempts(&self) -> u8 {
+        self.recovery.attempts
+    }
+
+    /// Resets the number of Bus_Off recovery attempts. This allows [`BusOffRecovery::BackOff`]
+    /// to recover after giving up.
+    #[inline]
+    pub fn reset_recovery_attempts(&mut self) {
+        self.recovery.attempts = 0;
+        if let RecoveryState::GaveUp = self.recovery.state {
+            self.recovery.state = RecoveryState::BusOff(self.recovery_delay());
+        }
+    }
+
+    /// Number of polls before the next attempt of [`BusOffRecovery::BackOff`]
+    fn recovery_delay(&self) -> u32 {
+        match self.config.bus_off_recovery {
+            BusOffRecovery::BackOff { delay, .. } => {
+                u32::from(delay) << self.recovery.attempts.min(16)
+            }
+            _ => 0,
+        }
+    }
+}
+
+#[cfg(feature = "fdcan_h7")]
+impl<I, M> FdCanControl<I, M>
+where
+    I: Instance,
+    M: Receive,
+{
+    /// Returns the dedicated Rx buffers holding new data. Bit `n` is set when buffer `n`
+    /// received a frame that was not read yet.
+    #[inline]
+    pub fn rx_buffers_with_new_data(&self) -> u64 {
+        let can = self.registers();
+        u64::from(can.ndat1.read().bits()) | u64::from(can.ndat2.read().bits()) << 32
+    }
+
+    /// Returns `true` if the dedicated Rx buffer `idx` holds new data
+    ///
+    /// # Panics
+    ///
+    /// Panics if `idx` is not within the Rx buffers of the Message RAM layout.
+    #[inline]
+    pub fn has_new_rx_buffer_data(&self, idx: u8) -> bool {
+        assert!(
+            idx < self.config.message_ram_layout.rx_buffers,
+            "Rx buffer is not in the Message RAM layout"
+        );
+        self.rx_buffers_with_new_data() & (1 << idx) != 0
+    }
+
+    #[inline]
+    fn clear_new_rx_buffer_data(&mut self, idx: u8) {
+        let can = self.registers();
+        if idx < 32 {
+            can.ndat1.write(|w| unsafe { w.bits(1 << idx) });
+        } else {
+            can.ndat2.write(|w| unsafe { w.bits(1 << (idx - 32)) });
+        }
+    }
+
+    /// Reads the frame in the dedicated Rx buffer `idx` and clears its new data flag.
+    ///
+    /// Dedicated Rx buffers hold the latest frame accepted by a filter with
+    /// [`Action::StoreInRxBuffer`](filter::Action::StoreInRxBuffer). Returns `WouldBlock` when
+    /// the buffer holds no new data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `idx` is not within the Rx buffers of the Message RAM layout, or if `buffer`
+    /// is smaller than the received data.
+    pub fn read_rx_buffer(
+        &mut self,
+        idx: u8,
+        buffer: &mut [u8],
+    ) -> nb::Result<RxFrameInfo, Infallible> {
+        assert!(
+            idx < self.config.message_ram_layout.rx_buffers,
+            "Rx buffer is not in the Message RAM layout"
+        );
+        if !self.has_new_rx_buffer_data(idx) {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let data_words = message_ram::rx_buffer_data_words::<I>();
+        loop {
+            // Clear the flag first, so a frame that arrives while reading is noticed and
+            // read again instead of returning a mix of both frames.
+            self.clear_new_rx_buffer_data(idx);
+            let element = unsafe { message_ram::rx_buffer_element::<I>(idx) };
+            let header = read_rx_element(element, data_words, buffer);
+            if !self.has_new_rx_buffer_data(idx) {
+                break Ok(header);
+            }
+        }
+    }
+}
+
 /// Interface to the CAN transmitter part.
 pub struct Tx<I, MODE> {
     _can: PhantomData<I>,
@@ -1221,13 +2386,13 @@ where
     }
 
     #[inline]
-    fn tx_msg_ram(&self) -> &message_ram::Transmit {
-        unsafe { &(*I::MSG_RAM).transmit }
+    fn tx_buffer(&self, idx: Mailbox) -> &message_ram::TxBufferElement {
+        unsafe { message_ram::tx_buffer_element::<I>(idx.into()) }
     }
 
     #[inline]
-    fn tx_msg_ram_mut(&mut self) -> &mut message_ram::Transmit {
-        unsafe { &mut (*I::MSG_RAM).transmit }
+    fn tx_buffer_mut(&mut self, idx: Mailbox) -> &mut message_ram::TxBufferElement {
+        unsafe { message_ram::tx_buffer_element::<I>(idx.into()) }
     }
 
     /// Puts a CAN frame in a transmit mailbox for transmission on the bus.
@@ -1239,6 +2404,9 @@ where
     /// frame, which is returned via the closure 'pending'. If 'pending' is called; it's return value
     /// is returned via `Option<P>`, if it is not, None is returned.
     /// If there are only higher priority frames in the queue, this returns Err::WouldBlock
+    ///
+    /// In [`TxBufferMode::Fifo`], frames are transmitted in the order they are put in the Tx
+    /// FIFO and are never replaced. If the FIFO is full, this returns Err::WouldBlock.
     pub fn transmit(
         &mut self,
         frame: TxFrameHeader,
@@ -1266,21 +2434,16 @@ where
         // If the queue is full,
         // Discard the first slot with a lower priority message
         let (idx, pending_frame) = if queue_is_full {
-            if self.is_available(Mailbox::_0, id) {
-                (
-                    Mailbox::_0,
-                    self.abort_pending_mailbox(Mailbox::_0, pending),
-                )
-            } else if self.is_available(Mailbox::_1, id) {
-                (
-                    Mailbox::_1,
-                    self.abort_pending_mailbox(Mailbox::_1, pending),
-                )
-            } else if self.is_available(Mailbox::_2, id) {
-                (
-                    Mailbox::_2,
-                    self.abort_pending_mailbox(Mailbox::_2, pending),
-                )
+            // Replacing a pending frame would reorder the FIFO
+            if can.txbc.read().tfqm().bit_is_clear() {
+                return Err(nb::Error::WouldBlock);
+            }
+
+            let available = message_ram::tx_queue::<I>()
+                .map(Mailbox::new)
+                .find(|&idx| self.is_available(idx, id));
+            if let Some(idx) = available {
+                (idx, self.abort_pending_mailbox(idx, pending))
             } else {
                 // For now we bail when there is no lower priority slot available
                 // Can this lead to priority inversion?
@@ -1298,6 +2461,78 @@ where
         Ok(pending_frame)
     }
 
+    /// Puts a CAN frame in the dedicated Tx buffer `idx` for transmission on the bus.
+    ///
+    /// Returns Err::WouldBlock while the previous frame in that buffer is still pending.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `idx` is not one of the dedicated Tx buffers of the Message RAM layout.
+    #[cfg(feature = "fdcan_h7")]
+    pub fn transmit_dedicated(
+        &mut self,
+        idx: Mailbox,
+        frame: TxFrameHeader,
+        buffer: &[u8],
+    ) -> nb::Result<(), Infallible> {
+        assert!(
+            u8::from(idx) < self.registers().txbc.read().ndtb().bits(),
+            "Not a dedicated Tx buffer"
+        );
+        if self.has_pending_frame(idx) {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.write_mailbox(idx, frame, buffer);
+        Ok(())
+    }
+
+    /// Reserves a free Tx buffer of the Tx FIFO/queue to build a frame in place, see
+    /// [`TxSlot`]. Returns Err::WouldBlock if the Tx FIFO/queue is full.
+    ///
+    /// Unlike [`Tx::transmit`], this never replaces a pending frame.
+    pub fn reserve(&mut self) -> nb::Result<TxSlot<'_, I, MODE>, Infallible> {
+        if self.tx_queue_is_full() {
+            return Err(nb::Error::WouldBlock);
+        }
+        let idx = Mailbox::new(self.registers().txfqs.read().tfqpi().bits());
+        Ok(TxSlot {
+            tx: self,
+            idx,
+            len: None,
+        })
+    }
+
+    /// Returns the status of the transmission requested last in the Tx buffer `idx`
+    pub fn buffer_status(&self, idx: Mailbox) -> TxBufferStatus {
+        let can = self.registers();
+        let idx: u8 = idx.into();
+        let idx: u32 = 1u32 << (idx as u32);
+
+        if can.txbrp.read().trp().bits() & idx != 0 {
+            TxBufferStatus::Pending
+        } else if can.txbto.read().to().bits() & idx != 0 {
+            TxBufferStatus::Transmitted
+        } else if can.txbcf.read().cf().bits() & idx != 0 {
+            TxBufferStatus::Cancelled
+        } else {
+            TxBufferStatus::Idle
+        }
+    }
+
+    /// Puts an owned [`Frame`] in a transmit mailbox for transmission on the bus.
+    ///
+    /// As [`Tx::transmit`], but a lower priority frame that has to make room for this one is
+    /// returned as a [`Frame`].
+    pub fn transmit_frame(
+        &mut self,
+        frame: &Frame,
+    ) -> nb::Result<Option<Frame>, Infallible> {
+        self.transmit_preserve(frame.tx_header(None), frame.data(), &mut |_, h, d| {
+            Frame::from_tx(h, d)
+        })
+    }
+
     /// Returns if the tx queue is able to accept new messages without having to cancel an existing one
     #[inline]
     pub fn tx_queue_is_full(&self) -> bool {
@@ -1310,8 +2545,7 @@ where
     fn is_available(&self, idx: Mailbox, id: IdReg) -> bool {
         if self.has_pending_frame(idx) {
             //read back header section
-            let header: TxFrameHeader =
-                (&self.tx_msg_ram().tbsa[idx as usize].header).into();
+            let header: TxFrameHeader = (&self.tx_buffer(idx).header).into();
             let old_id: IdReg = header.into();
 
             id > old_id
@@ -1322,24 +2556,29 @@ where
 
     #[inline]
     fn write_mailbox(&mut self, idx: Mailbox, tx_header: TxFrameHeader, buffer: &[u8]) {
-        let tx_ram = self.tx_msg_ram_mut();
+        let data_words = message_ram::tx_buffer_data_words::<I>();
+        assert!(
+            tx_header.len as usize <= data_words * 4,
+            "Frame does not fit into the Tx buffer element"
+        );
 
-        let tx_element = &mut tx_ram.tbsa[idx as usize];
+        let tx_element = self.tx_buffer_mut(idx);
 
         // Clear mail slot; mainly for debugging purposes.
-        tx_element.reset();
+        tx_element.reset_words(data_words);
         tx_element.header.merge(tx_header);
 
-        let mut lbuffer = [0_u32; 16];
-        let data = unsafe {
-            slice::from_raw_parts_mut(
-                lbuffer.as_mut_ptr() as *mut u8,
-                tx_header.len as usize,
-            )
+        // Remote frames carry no data
+        let len = if tx_header.rtr {
+            0
+        } else {
+            tx_header.len as usize
         };
-        data[..tx_header.len as usize]
-            .copy_from_slice(&buffer[..tx_header.len as usize]);
-        let data_len = ((tx_header.len as usize) + 3) / 4;
+        let mut lbuffer = [0_u32; 16];
+        let data =
+            unsafe { slice::from_raw_parts_mut(lbuffer.as_mut_ptr() as *mut u8, len) };
+        data[..len].copy_from_slice(&buffer[..len]);
+        let data_len = (len + 3) / 4;
         for (register, byte) in
             tx_element.data.iter_mut().zip(lbuffer[..data_len].iter())
         {
@@ -1347,6 +2586,11 @@ where
         }
 
         // Set <idx as Mailbox> as ready to transmit
+        self.add_request(idx);
+    }
+
+    #[inline]
+    fn add_request(&mut self, idx: Mailbox) {
         self.registers()
             .txbar
             .modify(|r, w| unsafe { w.ar().bits(r.ar().bits() | 1 << (idx as u32)) });
@@ -1358,13 +2602,14 @@ where
         PTX: FnOnce(Mailbox, TxFrameHeader, &[u32]) -> R,
     {
         if self.abort(idx) {
-            let tx_ram = self.tx_msg_ram();
+            let data_words = message_ram::tx_buffer_data_words::<I>();
+            let tx_element = self.tx_buffer(idx);
 
             //read back header section
-            let header = (&tx_ram.tbsa[idx as usize].header).into();
+            let header = (&tx_element.header).into();
             let mut data = [0u32; 16];
             for (byte, register) in
-                data.iter_mut().zip(tx_ram.tbsa[idx as usize].data.iter())
+                data.iter_mut().zip(tx_element.data[..data_words].iter())
             {
                 *byte = register.read();
             }
@@ -1438,6 +2683,83 @@ where
         let can = self.registers();
         can.ir.write(|w| w.tcf().set_bit());
     }
+
+    /// Returns the oldest event of the Tx Event FIFO, if any.
+    ///
+    /// Events are only stored for frames transmitted with a
+    /// [`marker`](TxFrameHeader::marker). As the FIFO is no longer full after this, the
+    /// TxEventFull interrupt flag is cleared.
+    pub fn pop_event(&mut self) -> Option<TxEvent> {
+        let can = self.registers();
+        let txefs = can.txefs.read();
+        if txefs.effl().bits() == 0 {
+            return None;
+        }
+
+        let idx = txefs.efgi().bits();
+        let event = unsafe { (&*message_ram::tx_event_element::<I>(idx)).into() };
+        can.txefa.write(|w| unsafe { w.efai().bits(idx) });
+        can.ir
+            .write(|w| unsafe { w.bits(Interrupts::TX_EVENT_FULL.bits()) });
+
+        Some(event)
+    }
+
+    /// Returns an iterator that pops all events of the Tx Event FIFO
+    #[inline]
+    pub fn tx_events(&mut self) -> TxEvents<'_, I, MODE> {
+        TxEvents { tx: self }
+    }
+
+    /// Returns `true` if Tx events were lost because the Tx Event FIFO was full, and clears
+    /// the TxEventLost interrupt flag.
+    #[inline]
+    pub fn tx_events_lost(&mut self) -> bool {
+        let can = self.registers();
+        let lost = can.txefs.read().tefl().bit();
+        can.ir
+            .write(|w| unsafe { w.bits(Interrupts::TX_EVENT_LOST.bits()) });
+        lost
+    }
+}
+
+/// Iterator over the events of the Tx Event FIFO, see [`Tx::tx_events`]
+pub struct TxEvents<'a, I, MODE> {
+    tx: &'a mut Tx<I, MODE>,
+}
+impl<'a, I, MODE> Iterator for TxEvents<'a, I, MODE>
+where
+    I: Instance,
+{
+    type Item = TxEvent;
+
+    #[inline]
+    fn next(&mut self) -> Option<TxEvent> {
+        self.tx.pop_event()
+    }
+}
+
+/// Copies the header and data of a received frame out of an Rx FIFO or Rx buffer element
+fn read_rx_element(
+    element: &RxFifoElement,
+    data_words: usize,
+    buffer: &mut [u8],
+) -> RxFrameInfo {
+    let header: RxFrameInfo = (&element.header).into();
+    for (i, register) in element.data[..data_words].iter().enumerate() {
+        let register_value = register.read();
+        let register_bytes = unsafe {
+            slice::from_raw_parts(&register_value as *const u32 as *const u8, 4)
+        };
+        let num_bytes = (header.len as usize) - i * 4;
+        if num_bytes <= 4 {
+            buffer[i * 4..i * 4 + num_bytes]
+                .copy_from_slice(&register_bytes[..num_bytes]);
+            break;
+        }
+        buffer[i * 4..(i + 1) * 4].copy_from_slice(register_bytes);
+    }
+    header
 }
 
 #[doc(hidden)]
@@ -1527,25 +2849,10 @@ where
         buffer: &mut [u8],
     ) -> nb::Result<ReceiveOverrun<RxFrameInfo>, Infallible> {
         if !self.rx_fifo_is_empty() {
-            let mbox = self.get_rx_mailbox();
-            let idx: usize = mbox.into();
-            let mailbox: &RxFifoElement = &self.rx_msg_ram().fxsa[idx];
-
-            let header: RxFrameInfo = (&mailbox.header).into();
-            for (i, register) in mailbox.data.iter().enumerate() {
-                let register_value = register.read();
-                let register_bytes = unsafe {
-                    slice::from_raw_parts(&register_value as *const u32 as *const u8, 4)
-                };
-                let num_bytes = (header.len as usize) - i * 4;
-                if num_bytes <= 4 {
-                    buffer[i * 4..i * 4 + num_bytes]
-                        .copy_from_slice(&register_bytes[..num_bytes]);
-                    break;
-                }
-                buffer[i * 4..(i + 1) * 4].copy_from_slice(register_bytes);
-            }
-            self.release_mailbox(mbox);
+            let idx = self.get_rx_mailbox();
+            let data_words = message_ram::rx_fifo_data_words::<I>(FIFONR::NR);
+            let header = read_rx_element(self.rx_element(idx), data_words, buffer);
+            self.release_mailbox(idx);
 
             if self.has_overrun() {
                 Ok(ReceiveOverrun::<RxFrameInfo>::Overrun(header))
@@ -1557,14 +2864,79 @@ where
         }
     }
 
+    /// Reads the last high priority message if it is stored in this FIFO and was not received
+    /// yet, ahead of the frames before it.
+    ///
+    /// The message stays in the FIFO and is returned again by [`Rx::receive`], as releasing it
+    /// would also release all frames before it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is smaller than the header length.
+    pub fn receive_high_priority(
+        &mut self,
+        buffer: &mut [u8],
+    ) -> nb::Result<RxFrameInfo, Infallible> {
+        let hpms = self.registers().hpms.read();
+        if usize::from(hpms.msi().bits()) != 0b10 + FIFONR::NR {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        let idx = hpms.bidx().bits();
+        let size = message_ram::rx_fifo_size::<I>(FIFONR::NR);
+        let fill_level = self.rx_fill_level();
+        if (idx + size - self.get_rx_mailbox()) % size >= fill_level {
+            return Err(nb::Error::WouldBlock);
+        }
+        let data_words = message_ram::rx_fifo_data_words::<I>(FIFONR::NR);
+        Ok(read_rx_element(self.rx_element(idx), data_words, buffer))
+    }
+
+    /// Returns a received frame if available, borrowing its data in the Message RAM.
+    ///
+    /// Unlike [`Rx::receive`], this neither copies the data nor clears the element. The frame
+    /// is released when the returned [`RxFrameRef`] is dropped.
+    pub fn receive_ref(
+        &mut self,
+    ) -> nb::Result<ReceiveOverrun<RxFrameRef<'_, I, MODE, FIFONR>>, Infallible> {
+        if self.rx_fifo_is_empty() {
+            return Err(nb::Error::WouldBlock);
+        }
+        let idx = self.get_rx_mailbox();
+        let info = (&self.rx_element(idx).header).into();
+        let overrun = self.has_overrun();
+        let frame = RxFrameRef {
+            rx: self,
+            idx,
+            info,
+        };
+        Ok(match overrun {
+            false => ReceiveOverrun::NoOverrun(frame),
+            true => ReceiveOverrun::Overrun(frame),
+        })
+    }
+
+    /// Returns a received frame as an owned [`Frame`] if available.
+    pub fn receive_frame(&mut self) -> nb::Result<ReceiveOverrun<Frame>, Infallible> {
+        let mut buffer = [0_u8; 64];
+        Ok(match self.receive(&mut buffer)? {
+            ReceiveOverrun::NoOverrun(info) => {
+                ReceiveOverrun::NoOverrun(Frame::from_rx(info, &buffer))
+            }
+            ReceiveOverrun::Overrun(info) => {
+                ReceiveOverrun::Overrun(Frame::from_rx(info, &buffer))
+            }
+        })
+    }
+
     #[inline]
     fn registers(&self) -> &RegisterBlock {
         unsafe { &*I::REGISTERS }
     }
 
     #[inline]
-    fn rx_msg_ram(&self) -> &message_ram::Receive {
-        unsafe { &(&(*I::MSG_RAM).receive)[FIFONR::NR] }
+    fn rx_element(&self, idx: u8) -> &RxFifoElement {
+        unsafe { message_ram::rx_fifo_element::<I>(FIFONR::NR, idx) }
     }
 
     #[inline]
@@ -1577,66 +2949,252 @@ where
         }
     }
 
+    /// Clears the message lost flag of the Rx FIFO, which is reported as an overrun
+    #[cfg(feature = "buffered")]
+    #[inline]
+    pub(crate) fn clear_overrun(&mut self) {
+        let lost = match FIFONR::NR {
+            0 => Interrupt::RxFifo0MsgLost,
+            _ => Interrupt::RxFifo1MsgLost,
+        };
+        self.registers()
+            .ir
+            .write(|w| unsafe { w.bits(lost as u32) });
+    }
+
     /// Returns if the fifo contains any new messages.
     #[inline]
     pub fn rx_fifo_is_empty(&self) -> bool {
+        self.rx_fill_level() == 0
+    }
+
+    #[inline]
+    fn rx_fill_level(&self) -> u8 {
         let can = self.registers();
         match FIFONR::NR {
-            0 => can.rxf0s.read().f0fl().bits() == 0,
-            1 => can.rxf1s.read().f1fl().bits() == 0,
+            0 => can.rxf0s.read().f0fl().bits(),
+            1 => can.rxf1s.read().f1fl().bits(),
             _ => unreachable!(),
         }
     }
 
     #[inline]
-    fn release_mailbox(&mut self, idx: Mailbox) {
+    fn release_mailbox(&mut self, idx: u8) {
+        let data_words = message_ram::rx_fifo_data_words::<I>(FIFONR::NR);
         unsafe {
-            (*I::MSG_RAM).receive[FIFONR::NR].fxsa[idx as u8 as usize].reset();
+            message_ram::rx_fifo_element::<I>(FIFONR::NR, idx).reset_words(data_words);
         }
+        self.acknowledge(idx);
+    }
 
+    #[inline]
+    fn acknowledge(&mut self, idx: u8) {
         let can = self.registers();
         match FIFONR::NR {
-            0 => can.rxf0a.write(|w| unsafe { w.f0ai().bits(idx.into()) }),
-            1 => can.rxf1a.write(|w| unsafe { w.f1ai().bits(idx.into()) }),
+            0 => can.rxf0a.write(|w| unsafe { w.f0ai().bits(idx) }),
+            1 => can.rxf1a.write(|w| unsafe { w.f1ai().bits(idx) }),
             _ => unreachable!(),
         }
     }
 
     #[inline]
-    fn get_rx_mailbox(&self) -> Mailbox {
+    fn get_rx_mailbox(&self) -> u8 {
         let can = self.registers();
-        let idx = match FIFONR::NR {
+        match FIFONR::NR {
             0 => can.rxf0s.read().f0gi().bits(),
             1 => can.rxf1s.read().f1gi().bits(),
             _ => unreachable!(),
-        };
-        Mailbox::new(idx)
+        }
     }
 }
 
-/// The three mailboxes.
-/// These are used for the transmit queue
-/// and the two Receive FIFOs
-#[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
-pub enum Mailbox {
-    /// Transmit mailbox 0
-    _0 = 0,
-    /// Transmit mailbox 1
-    _1 = 1,
-    /// Transmit mailbox 2
-    _2 = 2,
+/// A reserved Tx buffer to build a frame in the Message RAM, returned by [`Tx::reserve`]
+///
+/// Write the header with [`TxSlot::set_header`] and the data words with [`TxSlot::write_word`],
+/// in any order, then request the transmission with [`TxSlot::commit`]. Dropping the slot
+/// without committing leaves the Tx buffer free.
+pub struct TxSlot<'a, I, MODE>
+where
+    I: Instance,
+{
+    tx: &'a mut Tx<I, MODE>,
+    idx: Mailbox,
+    len: Option<u8>,
 }
-impl Mailbox {
+
+impl<'a, I, MODE> TxSlot<'a, I, MODE>
+where
+    I: Instance,
+{
+    /// The reserved Tx buffer
     #[inline]
-    fn new(idx: u8) -> Self {
-        match idx & 0b11 {
-            0 => Mailbox::_0,
-            1 => Mailbox::_1,
-            2 => Mailbox::_2,
-            _ => unreachable!(),
+    pub fn mailbox(&self) -> Mailbox {
+        self.idx
+    }
+
+    /// Writes the header of the frame
+    ///
+    /// # Panics
+    ///
+    /// Panics if the frame does not fit into the Tx buffer element.
+    pub fn set_header(&mut self, header: TxFrameHeader) {
+        let data_words = message_ram::tx_buffer_data_words::<I>();
+        assert!(
+            header.len as usize <= data_words * 4,
+            "Frame does not fit into the Tx buffer element"
+        );
+        self.tx.tx_buffer_mut(self.idx).header.merge(header);
+        self.len = Some(header.len);
+    }
+
+    /// Writes data word `idx` of the frame. The payload bytes are stored in the words in
+    /// little-endian order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `idx` is not within the data field of the Tx buffer element.
+    #[inline]
+    pub fn write_word(&mut self, idx: usize, word: u32) {
+        let data_words = message_ram::tx_buffer_data_words::<I>();
+        assert!(
+            idx < data_words,
+            "Data word is not in the Tx buffer element"
+        );
+        unsafe { self.tx.tx_buffer_mut(self.idx).data[idx].write(word) };
+    }
+
+    /// Writes `words` as the data words of the frame, starting at the first word
+    ///
+    /// # Panics
+    ///
+    /// Panics if `words` does not fit into the data field of the Tx buffer element.
+    #[inline]
+    pub fn write_words(&mut self, words: &[u32]) {
+        for (idx, &word) in words.iter().enumerate() {
+            self.write_word(idx, word);
         }
     }
+
+    /// Requests the transmission of the frame and returns the Tx buffer used
+    ///
+    /// # Panics
+    ///
+    /// Panics if the header was not written.
+    pub fn commit(self) -> Mailbox {
+        assert!(self.len.is_some(), "TxSlot header was not written");
+        self.tx.add_request(self.idx);
+        self.idx
+    }
 }
+
+/// A received frame in the Message RAM, returned by [`Rx::receive_ref`]
+///
+/// The frame is released from the Rx FIFO when this is dropped.
+pub struct RxFrameRef<'a, I, MODE, FIFONR>
+where
+    I: Instance,
+    FIFONR: FifoNr,
+{
+    rx: &'a mut Rx<I, MODE, FIFONR>,
+    idx: u8,
+    info: RxFrameInfo,
+}
+
+impl<'a, I, MODE, FIFONR> RxFrameRef<'a, I, MODE, FIFONR>
+where
+    I: Instance,
+    FIFONR: FifoNr,
+{
+    /// Header of the frame
+    #[inline]
+    pub fn info(&self) -> RxFrameInfo {
+        self.info
+    }
+
+    /// Number of data words holding the payload
+    #[inline]
+    pub fn len_words(&self) -> usize {
+        let data_words = message_ram::rx_fifo_data_words::<I>(FIFONR::NR);
+        let len = self.info.len as usize;
+        (len / 4 + (len % 4).min(1)).min(data_words)
+    }
+
+    /// Reads data word `idx` from the Message RAM. The payload bytes are stored in the words in
+    /// little-endian order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `idx` is not below [`RxFrameRef::len_words`].
+    #[inline]
+    pub fn word(&self, idx: usize) -> u32 {
+        assert!(idx < self.len_words(), "Data word is not part of the frame");
+        self.rx.rx_element(self.idx).data[idx].read()
+    }
+
+    /// Returns an iterator that reads the data words from the Message RAM, see
+    /// [`RxFrameRef::word`]
+    #[inline]
+    pub fn words(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.len_words()).map(move |idx| self.word(idx))
+    }
+}
+
+impl<'a, I, MODE, FIFONR> Drop for RxFrameRef<'a, I, MODE, FIFONR>
+where
+    I: Instance,
+    FIFONR: FifoNr,
+{
+    #[inline]
+    fn drop(&mut self) {
+        self.rx.acknowledge(self.idx);
+    }
+}
+
+macro_rules! declare_mailboxes {
+    ($($index:literal),*) => {
+        paste::paste! {
+            /// The transmit mailboxes (Tx buffers). On H7 the dedicated Tx buffers come first,
+            /// followed by the buffers of the Tx FIFO/queue.
+            #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq)]
+            pub enum Mailbox {
+                $(
+                    #[doc = "Transmit mailbox " $index]
+                    [<_ $index>] = $index,
+                )*
+            }
+            impl Mailbox {
+                #[inline]
+                fn new(idx: u8) -> Self {
+                    match idx {
+                        $($index => Mailbox::[<_ $index>],)*
+                        _ => unreachable!(),
+                    }
+                }
+            }
+        }
+    };
+}
+
+/// Status of the transmission requested last in a Tx buffer
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TxBufferStatus {
+    /// No transmission was requested since the buffer was configured
+    Idle,
+    /// A transmission is pending
+    Pending,
+    /// The frame was transmitted
+    Transmitted,
+    /// The transmission was cancelled
+    Cancelled,
+}
+
+#[cfg(feature = "fdcan_g0_g4_l5")]
+declare_mailboxes!(0, 1, 2);
+#[cfg(feature = "fdcan_h7")]
+declare_mailboxes!(
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22,
+    23, 24, 25, 26, 27, 28, 29, 30, 31
+);
 impl From<Mailbox> for u8 {
     #[inline]
     fn from(m: Mailbox) -> Self {
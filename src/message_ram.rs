@@ -3,18 +3,38 @@ use volatile_register::RW;
 pub(crate) mod common;
 pub(crate) mod enums;
 pub(crate) mod generic;
+#[cfg(feature = "fdcan_h7")]
+mod layout;
+#[cfg(feature = "fdcan_h7")]
+pub use layout::{check_disjoint, DataFieldSize, MessageRamLayout, MESSAGE_RAM_WORDS};
+#[cfg(feature = "fdcan_h7")]
+mod shared;
+#[cfg(feature = "fdcan_h7")]
+pub(crate) use shared::{check, claim, release};
+#[cfg(feature = "fdcan_h7")]
+pub use shared::{MessageRamError, MAX_INSTANCES};
 
 /// Number of Receive Fifos configured by this module
 pub const RX_FIFOS_MAX: u8 = 2;
 /// Number of Receive Messages per RxFifo configured by this module
+///
+/// On H7 this is the size used by the default `MessageRamLayout`.
 pub const RX_FIFO_MAX: u8 = 3;
 /// Number of Transmit Messages configured by this module
+///
+/// On H7 this is the size used by the default `MessageRamLayout`.
 pub const TX_FIFO_MAX: u8 = 3;
 /// Number of Transmit Events configured by this module
+///
+/// On H7 this is the size used by the default `MessageRamLayout`.
 pub const TX_EVENT_MAX: u8 = 3;
 /// Number of Standard Filters configured by this module
+///
+/// On H7 this is the size used by the default `MessageRamLayout`.
 pub const STANDARD_FILTER_MAX: u8 = 28;
 /// Number of Extended Filters configured by this module
+///
+/// On H7 this is the size used by the default `MessageRamLayout`.
 pub const EXTENDED_FILTER_MAX: u8 = 8;
 
 /// MessageRam Overlay
@@ -106,8 +126,13 @@ pub(crate) struct RxFifoElement {
 }
 impl RxFifoElement {
     pub(crate) fn reset(&mut self) {
+        self.reset_words(self.data.len());
+    }
+
+    /// Resets the header and the first `data_words` words of the data field
+    pub(crate) fn reset_words(&mut self, data_words: usize) {
         self.header.reset();
-        for byte in self.data.iter_mut() {
+        for byte in self.data[..data_words].iter_mut() {
             unsafe { byte.write(0) };
         }
     }
@@ -127,8 +152,13 @@ pub(crate) struct TxBufferElement {
 }
 impl TxBufferElement {
     pub(crate) fn reset(&mut self) {
+        self.reset_words(self.data.len());
+    }
+
+    /// Resets the header and the first `data_words` words of the data field
+    pub(crate) fn reset_words(&mut self, data_words: usize) {
         self.header.reset();
-        for byte in self.data.iter_mut() {
+        for byte in self.data[..data_words].iter_mut() {
             unsafe { byte.write(0) };
         }
     }
@@ -150,6 +180,12 @@ impl generic::Writable for TxBufferElementHeader {}
 ///   other accesses to the register block.
 /// * `MSG_RAM` is a pointer to the Message RAM block and can be safely accessed
 /// for as long as ownership or a borrow of the implementing type is present.
+///
+/// On H7 the Message RAM is shared by all instances and `MSG_RAM` must point to the start of
+/// the Message RAM (not to the region of the instance). The region used by an instance is set by
+/// the `MessageRamLayout` in its [`FdCanConfig`](crate::config::FdCanConfig). An instance claims
+/// its region when it enters ConfigMode and releases it when it is freed, overlapping regions
+/// are rejected. An instance that is dropped instead of freed keeps its region claimed.
 pub unsafe trait Instance {
     const MSG_RAM: *mut RegisterBlock;
     fn msg_ram(&self) -> &RegisterBlock {
@@ -160,6 +196,249 @@ pub unsafe trait Instance {
     }
 }
 
+// Accessors for the elements of an FdCan instance. On G0/G4/L5 the sections are at the fixed
+// locations of the overlay above. On H7 their location and element sizes are read back from the
+// configuration registers, which are written from the `MessageRamLayout`.
+pub(crate) use access::*;
+
+/// Bit mask of all Tx buffers, dedicated Tx buffers and the Tx FIFO/queue
+#[inline]
+pub(crate) fn tx_buffer_mask<I: crate::Instance>() -> u32 {
+    match tx_queue::<I>().end {
+        0 => 0,
+        n => u32::MAX >> (32 - n as u32),
+    }
+}
+
+#[cfg(feature = "fdcan_g0_g4_l5")]
+#[allow(clippy::extra_unused_type_parameters)] // same signatures as on H7
+mod access {
+    use super::*;
+    use crate::Instance;
+    use core::ops::Range;
+
+    #[inline]
+    pub(crate) unsafe fn standard_filter<'a, I: Instance>(
+        idx: u8,
+    ) -> &'a mut StandardFilter {
+        &mut (*I::MSG_RAM).filters.flssa[idx as usize]
+    }
+
+    #[inline]
+    pub(crate) unsafe fn extended_filter<'a, I: Instance>(
+        idx: u8,
+    ) -> &'a mut ExtendedFilter {
+        &mut (*I::MSG_RAM).filters.flesa[idx as usize]
+    }
+
+    #[inline]
+    pub(crate) unsafe fn rx_fifo_element<'a, I: Instance>(
+        fifo: usize,
+        idx: u8,
+    ) -> &'a mut RxFifoElement {
+        &mut (*I::MSG_RAM).receive[fifo].fxsa[idx as usize]
+    }
+
+    #[inline]
+    pub(crate) fn rx_fifo_data_words<I: Instance>(_fifo: usize) -> usize {
+        16
+    }
+
+    #[inline]
+    pub(crate) fn rx_fifo_size<I: Instance>(_fifo: usize) -> u8 {
+        RX_FIFO_MAX
+    }
+
+    #[inline]
+    pub(crate) unsafe fn tx_buffer_element<'a, I: Instance>(
+        idx: u8,
+    ) -> &'a mut TxBufferElement {
+        &mut (*I::MSG_RAM).transmit.tbsa[idx as usize]
+    }
+
+    #[inline]
+    pub(crate) fn tx_buffer_data_words<I: Instance>() -> usize {
+        16
+    }
+
+    #[inline]
+    pub(crate) unsafe fn tx_event_element<'a, I: Instance>(
+        idx: u8,
+    ) -> &'a mut TxEventElement {
+        &mut (*I::MSG_RAM).transmit.efsa[idx as usize]
+    }
+
+    #[inline]
+    pub(crate) fn standard_filters<I: Instance>() -> u8 {
+        STANDARD_FILTER_MAX
+    }
+
+    #[inline]
+    pub(crate) fn extended_filters<I: Instance>() -> u8 {
+        EXTENDED_FILTER_MAX
+    }
+
+    /// Indices of the Tx buffers used by the Tx queue
+    #[inline]
+    pub(crate) fn tx_queue<I: Instance>() -> Range<u8> {
+        0..TX_FIFO_MAX
+    }
+}
+
+#[cfg(feature = "fdcan_h7")]
+mod access {
+    use super::*;
+    use crate::Instance;
+    use core::ops::Range;
+
+    #[inline]
+    fn registers<'a, I: Instance>() -> &'a crate::RegisterBlock {
+        unsafe { &*I::REGISTERS }
+    }
+
+    /// Pointer to the word at `offset` words from the start of the Message RAM
+    #[inline]
+    unsafe fn word<I: Instance>(offset: u16) -> *mut u32 {
+        (I::MSG_RAM as *mut u32).add(offset as usize)
+    }
+
+    #[inline]
+    pub(crate) unsafe fn standard_filter<'a, I: Instance>(
+        idx: u8,
+    ) -> &'a mut StandardFilter {
+        let start = registers::<I>().sidfc.read().flssa().bits();
+        &mut *(word::<I>(start) as *mut StandardFilter).add(idx as usize)
+    }
+
+    #[inline]
+    pub(crate) unsafe fn extended_filter<'a, I: Instance>(
+        idx: u8,
+    ) -> &'a mut ExtendedFilter {
+        let start = registers::<I>().xidfc.read().flesa().bits();
+        &mut *(word::<I>(start) as *mut ExtendedFilter).add(idx as usize)
+    }
+
+    #[inline]
+    fn rx_fifo_config<I: Instance>(fifo: usize) -> (u16, DataFieldSize) {
+        let can = registers::<I>();
+        let rxesc = can.rxesc.read();
+        match fifo {
+            0 => (
+                can.rxf0c.read().f0sa().bits(),
+                DataFieldSize::from_bits(rxesc.f0ds().bits()),
+            ),
+            1 => (
+                can.rxf1c.read().f1sa().bits(),
+                DataFieldSize::from_bits(rxesc.f1ds().bits()),
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline]
+    pub(crate) unsafe fn rx_fifo_element<'a, I: Instance>(
+        fifo: usize,
+        idx: u8,
+    ) -> &'a mut RxFifoElement {
+        let (start, size) = rx_fifo_config::<I>(fifo);
+        let offset = start + idx as u16 * size.element_words();
+        &mut *(word::<I>(offset) as *mut RxFifoElement)
+    }
+
+    #[inline]
+    pub(crate) fn rx_fifo_data_words<I: Instance>(fifo: usize) -> usize {
+        rx_fifo_config::<I>(fifo).1.data_words() as usize
+    }
+
+    #[inline]
+    pub(crate) fn rx_fifo_size<I: Instance>(fifo: usize) -> u8 {
+        let can = registers::<I>();
+        match fifo {
+            0 => can.rxf0c.read().f0s().bits(),
+            1 => can.rxf1c.read().f1s().bits(),
+            _ => unreachable!(),
+        }
+    }
+
+    #[inline]
+    fn rx_buffer_size<I: Instance>() -> DataFieldSize {
+        DataFieldSize::from_bits(registers::<I>().rxesc.read().rbds().bits())
+    }
+
+    /// Dedicated Rx buffer elements use the same format as Rx FIFO elements
+    #[inline]
+    pub(crate) unsafe fn rx_buffer_element<'a, I: Instance>(
+        idx: u8,
+    ) -> &'a mut RxFifoElement {
+        let start = registers::<I>().rxbc.read().rbsa().bits();
+        let offset = start + idx as u16 * rx_buffer_size::<I>().element_words();
+        &mut *(word::<I>(offset) as *mut RxFifoElement)
+    }
+
+    #[inline]
+    pub(crate) fn rx_buffer_data_words<I: Instance>() -> usize {
+        rx_buffer_size::<I>().data_words() as usize
+    }
+
+    #[inline]
+    pub(crate) unsafe fn tx_buffer_element<'a, I: Instance>(
+        idx: u8,
+    ) -> &'a mut TxBufferElement {
+        let can = registers::<I>();
+        let start = can.txbc.read().tbsa().bits();
+        let size = DataFieldSize::from_bits(can.txesc.read().tbds().bits());
+        let offset = start + idx as u16 * size.element_words();
+        &mut *(word::<I>(offset) as *mut TxBufferElement)
+    }
+
+    #[inline]
+    pub(crate) fn tx_buffer_data_words<I: Instance>() -> usize {
+        let tbds = registers::<I>().txesc.read().tbds().bits();
+        DataFieldSize::from_bits(tbds).data_words() as usize
+    }
+
+    #[inline]
+    pub(crate) unsafe fn tx_event_element<'a, I: Instance>(
+        idx: u8,
+    ) -> &'a mut TxEventElement {
+        let start = registers::<I>().txefc.read().efsa().bits();
+        &mut *(word::<I>(start) as *mut TxEventElement).add(idx as usize)
+    }
+
+    #[inline]
+    pub(crate) fn standard_filters<I: Instance>() -> u8 {
+        registers::<I>().sidfc.read().lss().bits()
+    }
+
+    #[inline]
+    pub(crate) fn extended_filters<I: Instance>() -> u8 {
+        registers::<I>().xidfc.read().lse().bits()
+    }
+
+    /// Indices of the Tx buffers used by the Tx queue
+    #[inline]
+    pub(crate) fn tx_queue<I: Instance>() -> Range<u8> {
+        let txbc = registers::<I>().txbc.read();
+        let start = txbc.ndtb().bits();
+        start..start + txbc.tfqs().bits()
+    }
+
+    /// Pointer to the first of the two words of TTCAN trigger memory element `idx`
+    #[inline]
+    pub(crate) unsafe fn trigger_memory_element<I: Instance>(idx: u8) -> *mut u32 {
+        let start = registers::<I>().tttmc.read().tmsa().bits();
+        word::<I>(start + 2 * idx as u16)
+    }
+
+    /// Zeroes the Message RAM region of `layout`
+    #[inline]
+    pub(crate) fn reset<I: Instance>(layout: &MessageRamLayout) {
+        for offset in layout.offset..layout.end() {
+            unsafe { core::ptr::write_volatile(word::<I>(offset), 0) };
+        }
+    }
+}
+
 // Ensure the RegisterBlock is the same size as on pg 1957 of RM0440.
 static_assertions::assert_eq_size!(Filters, [u32; 28 + 16]);
 static_assertions::assert_eq_size!(Receive, [u32; 54]);
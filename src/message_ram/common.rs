@@ -127,6 +127,8 @@ impl ESFEC_R {
             0b100 => FilterElementConfig::SetPriority,
             0b101 => FilterElementConfig::SetPriorityAndStoreInFifo0,
             0b110 => FilterElementConfig::SetPriorityAndStoreInFifo1,
+            #[cfg(feature = "fdcan_h7")]
+            0b111 => FilterElementConfig::StoreInRxBuffer,
             _ => unimplemented!(),
         }
     }
@@ -31,6 +31,31 @@ impl DataLength {
         Self::new(len, FrameFormat::Fdcan)
     }
 
+    /// Creates a DataLength from the DLC field of a message header
+    pub(crate) fn from_dlc(dlc: u8, ff: FrameFormat) -> DataLength {
+        let len = if ff == FrameFormat::Fdcan {
+            // See RM0433 Rev 7 Table 475. DLC coding
+            match dlc {
+                0..=8 => dlc,
+                9 => 12,
+                10 => 16,
+                11 => 20,
+                12 => 24,
+                13 => 32,
+                14 => 48,
+                15 => 64,
+                _ => panic!("DLC > 15"),
+            }
+        } else {
+            match dlc {
+                0..=8 => dlc,
+                9..=15 => 8,
+                _ => panic!("DLC > 15"),
+            }
+        };
+        DataLength::new(len, ff)
+    }
+
     /// returns the length in bytes
     pub fn len(&self) -> u8 {
         match self {
@@ -227,5 +252,7 @@ pub enum FilterElementConfig {
     SetPriorityAndStoreInFifo0 = 0b101,
     /// Flag and store message in FIFO 1
     SetPriorityAndStoreInFifo1 = 0b110,
-    //_Unused = 0b111,
+    /// Store message in a dedicated Rx buffer (or as debug message)
+    #[cfg(feature = "fdcan_h7")]
+    StoreInRxBuffer = 0b111,
 }
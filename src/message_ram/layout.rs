@@ -0,0 +1,353 @@
+/// Size of the Message RAM shared by all FDCAN instances in 32-bit words (10 KiB)
+pub const MESSAGE_RAM_WORDS: u16 = 2560;
+
+/// Size of the data field of Rx FIFO, Rx buffer and Tx buffer elements
+///
+/// Frames with a larger payload than the element size of a FIFO or buffer cannot be stored in
+/// that FIFO or buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFieldSize {
+    /// 8 byte data field
+    _8Bytes = 0b000,
+    /// 12 byte data field
+    _12Bytes = 0b001,
+    /// 16 byte data field
+    _16Bytes = 0b010,
+    /// 20 byte data field
+    _20Bytes = 0b011,
+    /// 24 byte data field
+    _24Bytes = 0b100,
+    /// 32 byte data field
+    _32Bytes = 0b101,
+    /// 48 byte data field
+    _48Bytes = 0b110,
+    /// 64 byte data field
+    _64Bytes = 0b111,
+}
+impl DataFieldSize {
+    /// Size of the data field in bytes
+    pub const fn bytes(self) -> u8 {
+        match self {
+            DataFieldSize::_8Bytes => 8,
+            DataFieldSize::_12Bytes => 12,
+            DataFieldSize::_16Bytes => 16,
+            DataFieldSize::_20Bytes => 20,
+            DataFieldSize::_24Bytes => 24,
+            DataFieldSize::_32Bytes => 32,
+            DataFieldSize::_48Bytes => 48,
+            DataFieldSize::_64Bytes => 64,
+        }
+    }
+
+    /// Size of the data field in 32-bit words
+    pub(crate) const fn data_words(self) -> u16 {
+        self.bytes() as u16 / 4
+    }
+
+    /// Size of an element (header and data field) in 32-bit words
+    pub(crate) const fn element_words(self) -> u16 {
+        2 + self.data_words()
+    }
+
+    pub(crate) fn from_bits(bits: u8) -> Self {
+        match bits & 0b111 {
+            0b000 => DataFieldSize::_8Bytes,
+            0b001 => DataFieldSize::_12Bytes,
+            0b010 => DataFieldSize::_16Bytes,
+            0b011 => DataFieldSize::_20Bytes,
+            0b100 => DataFieldSize::_24Bytes,
+            0b101 => DataFieldSize::_32Bytes,
+            0b110 => DataFieldSize::_48Bytes,
+            _ => DataFieldSize::_64Bytes,
+        }
+    }
+}
+
+/// Layout of the Message RAM region used by an FDCAN instance
+///
+/// The sections are placed in the following order, starting at `offset`: standard filters,
+/// extended filters, Rx FIFO 0, Rx FIFO 1, Rx buffers, Tx event FIFO, Tx buffers and the TTCAN
+/// trigger memory. All offsets
+/// are in 32-bit words from the start of the Message RAM, which is where
+/// [`Instance::MSG_RAM`](super::Instance::MSG_RAM) points to.
+///
+/// Build the layout in a `const` to have [`MessageRamLayout::check`] reject layouts that do not
+/// fit at compile time:
+///
+/// ```ignore
+/// const LAYOUT: MessageRamLayout = MessageRamLayout::new()
+///     .set_standard_filters(128)
+///     .set_rx_fifo0(64, DataFieldSize::_64Bytes)
+///     .check();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageRamLayout {
+    /// Start of the region used by this instance
+    pub offset: u16,
+    /// Number of standard filter elements, 0 to 128
+    pub standard_filters: u8,
+    /// Number of extended filter elements, 0 to 64
+    pub extended_filters: u8,
+    /// Number of Rx FIFO 0 elements, 0 to 64
+    pub rx_fifo0_size: u8,
+    /// Data field size of the Rx FIFO 0 elements
+    pub rx_fifo0_data_size: DataFieldSize,
+    /// Number of Rx FIFO 1 elements, 0 to 64
+    pub rx_fifo1_size: u8,
+    /// Data field size of the Rx FIFO 1 elements
+    pub rx_fifo1_data_size: DataFieldSize,
+    /// Number of dedicated Rx buffer elements, 0 to 64
+    pub rx_buffers: u8,
+    /// Data field size of the dedicated Rx buffer elements
+    pub rx_buffer_data_size: DataFieldSize,
+    /// Number of Tx event FIFO elements, 0 to 32
+    pub tx_events: u8,
+    /// Number of dedicated Tx buffer elements. Together with `tx_buffers` at most 32
+    pub dedicated_tx_buffers: u8,
+    /// Number of Tx FIFO/queue buffer elements, 0 to 32
+    pub tx_buffers: u8,
+    /// Data field size of the Tx buffer elements
+    pub tx_buffer_data_size: DataFieldSize,
+    /// Number of TTCAN trigger memory elements, 0 to 64. Only used by FDCAN1
+    pub trigger_memory: u8,
+}
+
+impl MessageRamLayout {
+    /// The layout used by this module by default. This is the same layout as the fixed layout
+    /// found on the G0, G4 and L5 series.
+    pub const fn new() -> Self {
+        Self {
+            offset: 0,
+            standard_filters: super::STANDARD_FILTER_MAX,
+            extended_filters: super::EXTENDED_FILTER_MAX,
+            rx_fifo0_size: super::RX_FIFO_MAX,
+            rx_fifo0_data_size: DataFieldSize::_64Bytes,
+            rx_fifo1_size: super::RX_FIFO_MAX,
+            rx_fifo1_data_size: DataFieldSize::_64Bytes,
+            rx_buffers: 0,
+            rx_buffer_data_size: DataFieldSize::_64Bytes,
+            tx_events: super::TX_EVENT_MAX,
+            dedicated_tx_buffers: 0,
+            tx_buffers: super::TX_FIFO_MAX,
+            tx_buffer_data_size: DataFieldSize::_64Bytes,
+            trigger_memory: 0,
+        }
+    }
+
+    /// Sets the start of the region used by this instance
+    #[inline]
+    pub const fn set_offset(mut self, offset: u16) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Sets the number of standard filter elements
+    #[inline]
+    pub const fn set_standard_filters(mut self, n: u8) -> Self {
+        self.standard_filters = n;
+        self
+    }
+
+    /// Sets the number of extended filter elements
+    #[inline]
+    pub const fn set_extended_filters(mut self, n: u8) -> Self {
+        self.extended_filters = n;
+        self
+    }
+
+    /// Sets the number and data field size of the Rx FIFO 0 elements
+    #[inline]
+    pub const fn set_rx_fifo0(mut self, n: u8, size: DataFieldSize) -> Self {
+        self.rx_fifo0_size = n;
+        self.rx_fifo0_data_size = size;
+        self
+    }
+
+    /// Sets the number and data field size of the Rx FIFO 1 elements
+    #[inline]
+    pub const fn set_rx_fifo1(mut self, n: u8, size: DataFieldSize) -> Self {
+        self.rx_fifo1_size = n;
+        self.rx_fifo1_data_size = size;
+        self
+    }
+
+    /// Sets the number and data field size of the dedicated Rx buffer elements
+    #[inline]
+    pub const fn set_rx_buffers(mut self, n: u8, size: DataFieldSize) -> Self {
+        self.rx_buffers = n;
+        self.rx_buffer_data_size = size;
+        self
+    }
+
+    /// Sets the number of Tx event FIFO elements
+    #[inline]
+    pub const fn set_tx_events(mut self, n: u8) -> Self {
+        self.tx_events = n;
+        self
+    }
+
+    /// Sets the number of dedicated Tx buffer elements. These are placed before the Tx
+    /// FIFO/queue buffers and share their data field size.
+    #[inline]
+    pub const fn set_dedicated_tx_buffers(mut self, n: u8) -> Self {
+        self.dedicated_tx_buffers = n;
+        self
+    }
+
+    /// Sets the number and data field size of the Tx FIFO/queue buffer elements
+    #[inline]
+    pub const fn set_tx_buffers(mut self, n: u8, size: DataFieldSize) -> Self {
+        self.tx_buffers = n;
+        self.tx_buffer_data_size = size;
+        self
+    }
+
+    /// Sets the number of TTCAN trigger memory elements
+    #[inline]
+    pub const fn set_trigger_memory(mut self, n: u8) -> Self {
+        self.trigger_memory = n;
+        self
+    }
+
+    /// Places this layout directly after `other`, to share the Message RAM with the instance
+    /// using `other`
+    #[inline]
+    pub const fn place_after(self, other: &MessageRamLayout) -> Self {
+        self.set_offset(other.end())
+    }
+
+    /// Start of the standard filter list
+    #[inline]
+    pub const fn standard_filter_start(&self) -> u16 {
+        self.offset
+    }
+
+    /// Start of the extended filter list
+    #[inline]
+    pub const fn extended_filter_start(&self) -> u16 {
+        self.standard_filter_start() + self.standard_filters as u16
+    }
+
+    /// Start of Rx FIFO 0
+    #[inline]
+    pub const fn rx_fifo0_start(&self) -> u16 {
+        self.extended_filter_start() + 2 * self.extended_filters as u16
+    }
+
+    /// Start of Rx FIFO 1
+    #[inline]
+    pub const fn rx_fifo1_start(&self) -> u16 {
+        self.rx_fifo0_start()
+            + self.rx_fifo0_size as u16 * self.rx_fifo0_data_size.element_words()
+    }
+
+    /// Start of the dedicated Rx buffers
+    #[inline]
+    pub const fn rx_buffer_start(&self) -> u16 {
+        self.rx_fifo1_start()
+            + self.rx_fifo1_size as u16 * self.rx_fifo1_data_size.element_words()
+    }
+
+    /// Start of the Tx event FIFO
+    #[inline]
+    pub const fn tx_event_start(&self) -> u16 {
+        self.rx_buffer_start()
+            + self.rx_buffers as u16 * self.rx_buffer_data_size.element_words()
+    }
+
+    /// Start of the Tx buffers
+    #[inline]
+    pub const fn tx_buffer_start(&self) -> u16 {
+        self.tx_event_start() + 2 * self.tx_events as u16
+    }
+
+    /// Start of the TTCAN trigger memory
+    #[inline]
+    pub const fn trigger_memory_start(&self) -> u16 {
+        let tx_buffers = self.dedicated_tx_buffers as u16 + self.tx_buffers as u16;
+        self.tx_buffer_start() + tx_buffers * self.tx_buffer_data_size.element_words()
+    }
+
+    /// First word after the region used by this instance
+    #[inline]
+    pub const fn end(&self) -> u16 {
+        self.trigger_memory_start() + 2 * self.trigger_memory as u16
+    }
+
+    /// Size of the region used by this instance
+    #[inline]
+    pub const fn size(&self) -> u16 {
+        self.end() - self.offset
+    }
+
+    /// Returns `true` if the regions of both layouts overlap
+    #[inline]
+    pub const fn overlaps(&self, other: &MessageRamLayout) -> bool {
+        self.offset < other.end() && other.offset < self.end()
+    }
+
+    /// Checks that all sections are within the limits of the peripheral and that the layout
+    /// fits into the Message RAM. Returns the layout unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the layout is invalid. When evaluated in a `const`, this is a compile time
+    /// error.
+    pub const fn check(self) -> Self {
+        assert!(self.standard_filters <= 128, "At most 128 standard filters");
+        assert!(self.extended_filters <= 64, "At most 64 extended filters");
+        assert!(self.rx_fifo0_size <= 64, "At most 64 Rx FIFO 0 elements");
+        assert!(self.rx_fifo1_size <= 64, "At most 64 Rx FIFO 1 elements");
+        assert!(self.rx_buffers <= 64, "At most 64 dedicated Rx buffers");
+        assert!(self.tx_events <= 32, "At most 32 Tx event FIFO elements");
+        assert!(
+            self.dedicated_tx_buffers as u16 + self.tx_buffers as u16 <= 32,
+            "At most 32 Tx buffers"
+        );
+        assert!(
+            self.trigger_memory <= 64,
+            "At most 64 trigger memory elements"
+        );
+        assert!(
+            self.offset as u32 + self.size() as u32 <= MESSAGE_RAM_WORDS as u32,
+            "Layout does not fit into the Message RAM"
+        );
+        self
+    }
+}
+
+impl Default for MessageRamLayout {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Checks the layouts of all instances sharing the Message RAM, see
+/// [`MessageRamLayout::check`], and that no two of them overlap.
+///
+/// # Panics
+///
+/// Panics when a layout is invalid or when two layouts overlap. When evaluated in a `const`,
+/// this is a compile time error:
+///
+/// ```ignore
+/// const FDCAN1_LAYOUT: MessageRamLayout = MessageRamLayout::new();
+/// const FDCAN2_LAYOUT: MessageRamLayout = MessageRamLayout::new().place_after(&FDCAN1_LAYOUT);
+/// const _: () = check_disjoint(&[FDCAN1_LAYOUT, FDCAN2_LAYOUT]);
+/// ```
+pub const fn check_disjoint(layouts: &[MessageRamLayout]) {
+    let mut i = 0;
+    while i < layouts.len() {
+        layouts[i].check();
+        let mut j = i + 1;
+        while j < layouts.len() {
+            assert!(
+                !layouts[i].overlaps(&layouts[j]),
+                "Message RAM layouts overlap"
+            );
+            j += 1;
+        }
+        i += 1;
+    }
+}
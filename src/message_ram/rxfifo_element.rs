@@ -3,7 +3,7 @@
 #![allow(unused)]
 
 use super::common::{BRS_R, DLC_R, ESI_R, FDF_R, ID_R, RTR_R, XTD_R};
-use super::enums::{DataLength, FilterFrameMatch, FrameFormat};
+use super::enums::{DataLength, FilterFrameMatch};
 use super::generic;
 
 #[doc = "Reader of register RxFifoElement"]
@@ -87,29 +87,7 @@ impl R {
         ANMF_R::new(((self.bits[1] >> 31) & 0x01) != 0)
     }
     pub fn to_data_length(&self) -> DataLength {
-        let dlc = self.dlc().bits();
-        let ff = self.fdf().frame_format();
-        let len = if ff == FrameFormat::Fdcan {
-            // See RM0433 Rev 7 Table 475. DLC coding
-            match dlc {
-                0..=8 => dlc,
-                9 => 12,
-                10 => 16,
-                11 => 20,
-                12 => 24,
-                13 => 32,
-                14 => 48,
-                15 => 64,
-                _ => panic!("DLC > 15"),
-            }
-        } else {
-            match dlc {
-                0..=8 => dlc,
-                9..=15 => 8,
-                _ => panic!("DLC > 15"),
-            }
-        };
-        DataLength::new(len, ff)
+        DataLength::from_dlc(self.dlc().bits(), self.fdf().frame_format())
     }
     pub fn to_filter_match(&self) -> FilterFrameMatch {
         if self.anmf().is_matching_frame() {
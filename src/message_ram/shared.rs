@@ -0,0 +1,92 @@
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+use super::MessageRamLayout;
+
+/// Maximum number of FDCAN instances sharing the Message RAM
+pub const MAX_INSTANCES: usize = 3;
+
+/// Error returned when a Message RAM region cannot be claimed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageRamError {
+    /// The region overlaps the region claimed by another instance
+    Overlap,
+    /// More than [`MAX_INSTANCES`] instances claimed a region
+    TooManyInstances,
+}
+
+// Regions claimed by the instances. `OWNERS` holds the address of the register block of the
+// instance (0 when unused) and `REGIONS` the claimed region as `offset << 16 | end`. Both are
+// only accessed in a critical section, so instances can be claimed and released from interrupt
+// handlers as well.
+static OWNERS: [AtomicUsize; MAX_INSTANCES] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+static REGIONS: [AtomicU32; MAX_INSTANCES] =
+    [AtomicU32::new(0), AtomicU32::new(0), AtomicU32::new(0)];
+
+fn with_lock<R>(f: impl FnOnce() -> R) -> R {
+    critical_section::with(|_| f())
+}
+
+// Finds the slot of `owner`, or a free slot, and checks the region of `layout` against the
+// regions of the other instances. Must be called in a critical section.
+fn find_slot(
+    owner: usize,
+    layout: &MessageRamLayout,
+) -> Result<usize, MessageRamError> {
+    let (start, end) = (layout.offset as u32, layout.end() as u32);
+
+    let mut slot = None;
+    for (i, o) in OWNERS.iter().enumerate() {
+        match o.load(Ordering::Relaxed) {
+            0 => slot = slot.or(Some(i)),
+            o if o == owner => slot = Some(i),
+            _ => {
+                let region = REGIONS[i].load(Ordering::Relaxed);
+                if start < region & 0xffff && region >> 16 < end {
+                    return Err(MessageRamError::Overlap);
+                }
+            }
+        }
+    }
+    slot.ok_or(MessageRamError::TooManyInstances)
+}
+
+/// Checks that the region of `layout` could be claimed for the instance with the register
+/// block at `owner`, without claiming it.
+pub(crate) fn check(
+    owner: usize,
+    layout: &MessageRamLayout,
+) -> Result<(), MessageRamError> {
+    with_lock(|| find_slot(owner, layout).map(|_| ()))
+}
+
+/// Claims the region of `layout` for the instance with the register block at `owner`,
+/// replacing the region it claimed before.
+pub(crate) fn claim(
+    owner: usize,
+    layout: &MessageRamLayout,
+) -> Result<(), MessageRamError> {
+    with_lock(|| {
+        let i = find_slot(owner, layout)?;
+        OWNERS[i].store(owner, Ordering::Relaxed);
+        REGIONS[i].store(
+            (layout.offset as u32) << 16 | layout.end() as u32,
+            Ordering::Relaxed,
+        );
+        Ok(())
+    })
+}
+
+/// Releases the region claimed by the instance with the register block at `owner`
+pub(crate) fn release(owner: usize) {
+    with_lock(|| {
+        for o in OWNERS.iter() {
+            if o.load(Ordering::Relaxed) == owner {
+                o.store(0, Ordering::Relaxed);
+            }
+        }
+    })
+}
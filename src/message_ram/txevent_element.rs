@@ -3,6 +3,7 @@
 #![allow(unused)]
 
 use super::common::{BRS_R, DLC_R, ESI_R, RTR_R, XTD_R};
+use super::enums::{DataLength, FrameFormat};
 use super::generic;
 
 #[doc = "Reader of register TxEventElement"]
@@ -133,4 +134,12 @@ impl R {
     pub fn mm(&self) -> MM_R {
         MM_R::new(((self.bits[1] >> 24) & 0xFF) as u8)
     }
+    pub fn to_data_length(&self) -> DataLength {
+        let ff = if self.edl().is_fdcan_length() {
+            FrameFormat::Fdcan
+        } else {
+            FrameFormat::Standard
+        };
+        DataLength::from_dlc(self.dlc().bits(), ff)
+    }
 }
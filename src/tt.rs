@@ -0,0 +1,785 @@
+//! Time-triggered CAN (TTCAN, ISO 11898-4) level 1.
+//!
+//! Only FDCAN1 of the H7 series implements TTCAN, the HAL marks it by implementing
+//! [`TtInstance`]. The schedule of a node is a list of [`Trigger`]s, sorted by their time mark,
+//! that is written to the trigger memory together with a [`TtConfig`] in ConfigMode. The trigger
+//! memory is part of the Message RAM region of the instance, see
+//! [`MessageRamLayout::set_trigger_memory`]:
+//!
+//! ```ignore
+//! const SCHEDULE: &[Trigger] = &[
+//!     Trigger::tx_ref(0),
+//!     Trigger::rx_standard(100, StandardFilterSlot::_0),
+//!     Trigger::tx_single(200, Mailbox::_1).set_cycle(2, 1),
+//!     Trigger::watch(900),
+//! ];
+//! const _: () = check_schedule(SCHEDULE);
+//!
+//! let config = TtConfig::new(StandardId::new(0x10).unwrap().into()).set_time_master(true);
+//! can.set_time_triggered(config, SCHEDULE);
+//! ```
+//!
+//! Time-triggered operation starts when leaving ConfigMode. Frames of Tx triggers are put into
+//! dedicated Tx buffers with `transmit_dedicated`.
+
+use core::ptr;
+
+use crate::config::MessageRamLayout;
+use crate::filter::{ExtendedFilterSlot, StandardFilterSlot};
+use crate::id::Id;
+use crate::{message_ram, ConfigMode, FdCan, FdCanControl, Instance, Mailbox};
+
+/// An FdCan instance that implements time-triggered CAN.
+///
+/// # Safety
+///
+/// Only implement this trait for instances that have the TT registers, i.e. FDCAN1 on H7.
+pub unsafe trait TtInstance: Instance {}
+
+/// Type of a trigger in the trigger memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TriggerType {
+    /// Transmits the reference message, only used by potential time masters
+    TxRefTrigger = 0b0000,
+    /// As `TxRefTrigger`, but only valid when the preceding basic cycle ended with a gap
+    TxRefTriggerGap = 0b0001,
+    /// Transmits the frame of a Tx buffer in an exclusive time window, once
+    TxTriggerSingle = 0b0010,
+    /// Transmits the frame of a Tx buffer in an exclusive time window, in every matching cycle
+    TxTriggerContinuous = 0b0011,
+    /// Starts an arbitrating time window
+    TxTriggerArbitration = 0b0100,
+    /// Merges the time window with the preceding one
+    TxTriggerMerged = 0b0101,
+    /// Checks that a reference message was received, the cycle is restarted otherwise
+    WatchTrigger = 0b0110,
+    /// As `WatchTrigger`, but only valid in a gap
+    WatchTriggerGap = 0b0111,
+    /// Checks that a frame matching a filter element was received
+    RxTrigger = 0b1000,
+    /// Generates a time mark event without reference to a frame
+    TimeBaseTrigger = 0b1001,
+}
+
+/// An element of the trigger memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Trigger {
+    /// Cycle time at which the trigger becomes active, in NTU
+    pub time_mark: u16,
+    /// Basic cycles in which the trigger is active, see [`Trigger::set_cycle`]
+    pub cycle_code: u8,
+    /// Type of the trigger
+    pub trigger_type: TriggerType,
+    /// Tx buffer of Tx triggers, filter element of Rx triggers
+    pub message_number: u8,
+    /// The filter element of an Rx trigger is an extended filter
+    pub extended_filter: bool,
+    /// Sets the [`TtInterrupts::TRIGGER_TIME_MARK`] flag when the trigger becomes active
+    pub time_mark_event: bool,
+}
+
+impl Trigger {
+    const fn new(
+        time_mark: u16,
+        trigger_type: TriggerType,
+        message_number: u8,
+    ) -> Self {
+        Self {
+            time_mark,
+            cycle_code: 1,
+            trigger_type,
+            message_number,
+            extended_filter: false,
+            time_mark_event: false,
+        }
+    }
+
+    /// Transmits the reference message at `time_mark`
+    pub const fn tx_ref(time_mark: u16) -> Self {
+        Self::new(time_mark, TriggerType::TxRefTrigger, 0)
+    }
+
+    /// Transmits the frame in `buffer` once at `time_mark`
+    pub const fn tx_single(time_mark: u16, buffer: Mailbox) -> Self {
+        Self::new(time_mark, TriggerType::TxTriggerSingle, buffer as u8)
+    }
+
+    /// Transmits the frame in `buffer` at `time_mark` of every matching cycle
+    pub const fn tx_continuous(time_mark: u16, buffer: Mailbox) -> Self {
+        Self::new(time_mark, TriggerType::TxTriggerContinuous, buffer as u8)
+    }
+
+    /// Starts an arbitrating time window at `time_mark`, transmitting the frame in `buffer`
+    pub const fn tx_arbitration(time_mark: u16, buffer: Mailbox) -> Self {
+        Self::new(time_mark, TriggerType::TxTriggerArbitration, buffer as u8)
+    }
+
+    /// Merges the frame in `buffer` into the preceding arbitrating time window
+    pub const fn tx_merged(time_mark: u16, buffer: Mailbox) -> Self {
+        Self::new(time_mark, TriggerType::TxTriggerMerged, buffer as u8)
+    }
+
+    /// Checks at `time_mark` that a frame matching the standard filter `slot` was received
+    pub const fn rx_standard(time_mark: u16, slot: StandardFilterSlot) -> Self {
+        Self::new(time_mark, TriggerType::RxTrigger, slot as u8)
+    }
+
+    /// Checks at `time_mark` that a frame matching the extended filter `slot` was received
+    pub const fn rx_extended(time_mark: u16, slot: ExtendedFilterSlot) -> Self {
+        let mut t = Self::new(time_mark, TriggerType::RxTrigger, slot as u8);
+        t.extended_filter = true;
+        t
+    }
+
+    /// Generates a time mark event at `time_mark`
+    pub const fn time_base(time_mark: u16) -> Self {
+        Self::new(time_mark, TriggerType::TimeBaseTrigger, 0)
+    }
+
+    /// Restarts the cycle if no reference message was received until `time_mark`
+    pub const fn watch(time_mark: u16) -> Self {
+        Self::new(time_mark, TriggerType::WatchTrigger, 0)
+    }
+
+    /// Sets the trigger type, to use the gap variants of the triggers
+    pub const fn set_type(mut self, trigger_type: TriggerType) -> Self {
+        self.trigger_type = trigger_type;
+        self
+    }
+
+    /// Makes the trigger active in every `repeat`th basic cycle, starting with cycle `offset`.
+    /// By default triggers are active in all basic cycles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `repeat` is not a power of two up to 64, or `offset` is not smaller than
+    /// `repeat`.
+    pub const fn set_cycle(mut self, repeat: u8, offset: u8) -> Self {
+        assert!(
+            repeat.is_power_of_two() && repeat <= 64,
+            "Repeat factor must be a power of two up to 64"
+        );
+        assert!(
+            offset < repeat,
+            "Cycle offset must be smaller than the repeat factor"
+        );
+        self.cycle_code = repeat + offset;
+        self
+    }
+
+    /// Sets the [`TtInterrupts::TRIGGER_TIME_MARK`] flag when the trigger becomes active
+    pub const fn set_time_mark_event(mut self, enabled: bool) -> Self {
+        self.time_mark_event = enabled;
+        self
+    }
+
+    /// Repeat factor of the cycle code
+    const fn repeat(&self) -> u8 {
+        let mut repeat = 64;
+        while repeat > self.cycle_code {
+            repeat >>= 1;
+        }
+        repeat
+    }
+
+    const fn is_tx(&self) -> bool {
+        matches!(
+            self.trigger_type,
+            TriggerType::TxTriggerSingle
+                | TriggerType::TxTriggerContinuous
+                | TriggerType::TxTriggerArbitration
+                | TriggerType::TxTriggerMerged
+        )
+    }
+
+    /// The two words of the trigger memory element
+    const fn words(&self) -> [u32; 2] {
+        [
+            (self.time_mark as u32) << 16
+                | (self.cycle_code as u32 & 0x7f) << 8
+                | (self.time_mark_event as u32) << 7
+                | self.trigger_type as u32,
+            (self.extended_filter as u32) << 23
+                | (self.message_number as u32 & 0x7f) << 16,
+        ]
+    }
+}
+
+/// Checks that `schedule` fits into the trigger memory and is sorted by time mark.
+///
+/// # Panics
+///
+/// Panics when the schedule is invalid. When evaluated in a `const`, this is a compile time
+/// error.
+pub const fn check_schedule(schedule: &[Trigger]) {
+    assert!(schedule.len() <= 64, "At most 64 triggers");
+    let mut i = 1;
+    while i < schedule.len() {
+        assert!(
+            schedule[i - 1].time_mark <= schedule[i].time_mark,
+            "Triggers must be sorted by time mark"
+        );
+        i += 1;
+    }
+}
+
+/// Expected number of Tx triggers in a matrix cycle of `cycles` basic cycles
+const fn expected_tx_triggers(schedule: &[Trigger], cycles: u8) -> u16 {
+    let mut count = 0;
+    let mut i = 0;
+    while i < schedule.len() {
+        let t = &schedule[i];
+        if t.is_tx() {
+            let repeat = t.repeat();
+            let offset = t.cycle_code - repeat;
+            let mut cycle = 0;
+            while cycle < cycles {
+                if cycle % repeat == offset {
+                    count += 1;
+                }
+                cycle += 1;
+            }
+        }
+        i += 1;
+    }
+    count
+}
+
+/// Configuration of time-triggered operation
+#[derive(Clone, Copy, Debug)]
+pub struct TtConfig {
+    /// Identifier of the reference message. The three least significant bits are the time master
+    /// priority of potential time masters.
+    pub reference_id: Id,
+    /// Reference messages sent by this node carry the payload of Tx buffer 0
+    pub reference_payload: bool,
+    /// This node is a potential time master
+    pub time_master: bool,
+    /// Number of basic cycles in a matrix cycle: 1, 2, 4, 8, 16, 32 or 64
+    pub matrix_cycles: u8,
+    /// Length of the Tx enable window in NTU, 1 to 16
+    pub tx_enable_window: u8,
+    /// Offset of the first reference trigger after initialisation in NTU, 0 to 127
+    pub initial_ref_trigger_offset: u8,
+    /// Application watchdog limit in steps of 256 NTU. 0 disables the application watchdog.
+    pub application_watchdog_limit: u8,
+    /// Numerator of the time unit ratio, 0x1_0000 to 0x1_FFFF
+    pub tur_numerator: u32,
+    /// Denominator of the time unit ratio, 1 to 0x3FFF
+    pub tur_denominator: u16,
+}
+
+impl TtConfig {
+    /// A time slave with matrix cycles of one basic cycle and an NTU of 16 kernel clock periods
+    pub const fn new(reference_id: Id) -> Self {
+        Self {
+            reference_id,
+            reference_payload: false,
+            time_master: false,
+            matrix_cycles: 1,
+            tx_enable_window: 1,
+            initial_ref_trigger_offset: 0,
+            application_watchdog_limit: 0,
+            tur_numerator: 0x1_0000,
+            tur_denominator: 0x1000,
+        }
+    }
+
+    /// Appends the payload of Tx buffer 0 to reference messages sent by this node
+    #[inline]
+    pub const fn set_reference_payload(mut self, enabled: bool) -> Self {
+        self.reference_payload = enabled;
+        self
+    }
+
+    /// Makes this node a potential time master
+    #[inline]
+    pub const fn set_time_master(mut self, enabled: bool) -> Self {
+        self.time_master = enabled;
+        self
+    }
+
+    /// Sets the number of basic cycles in a matrix cycle
+    #[inline]
+    pub const fn set_matrix_cycles(mut self, cycles: u8) -> Self {
+        self.matrix_cycles = cycles;
+        self
+    }
+
+    /// Sets the length of the Tx enable window in NTU
+    #[inline]
+    pub const fn set_tx_enable_window(mut self, ntu: u8) -> Self {
+        self.tx_enable_window = ntu;
+        self
+    }
+
+    /// Sets the offset of the first reference trigger after initialisation in NTU
+    #[inline]
+    pub const fn set_initial_ref_trigger_offset(mut self, ntu: u8) -> Self {
+        self.initial_ref_trigger_offset = ntu;
+        self
+    }
+
+    /// Sets the application watchdog limit in steps of 256 NTU, 0 disables the watchdog. The
+    /// watchdog is served by [`FdCan::tt_status`].
+    #[inline]
+    pub const fn set_application_watchdog_limit(mut self, limit: u8) -> Self {
+        self.application_watchdog_limit = limit;
+        self
+    }
+
+    /// Sets the length of an NTU to `numerator / denominator` periods of the kernel clock
+    #[inline]
+    pub const fn set_time_unit_ratio(
+        mut self,
+        numerator: u32,
+        denominator: u16,
+    ) -> Self {
+        self.tur_numerator = numerator;
+        self.tur_denominator = denominator;
+        self
+    }
+
+    /// Checks that all values are within the limits of the peripheral. Returns the config
+    /// unchanged.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the config is invalid. When evaluated in a `const`, this is a compile time
+    /// error.
+    pub const fn check(self) -> Self {
+        assert!(
+            self.matrix_cycles.is_power_of_two() && self.matrix_cycles <= 64,
+            "Matrix cycles must be a power of two up to 64"
+        );
+        assert!(
+            self.tx_enable_window >= 1 && self.tx_enable_window <= 16,
+            "Tx enable window must be 1 to 16 NTU"
+        );
+        assert!(
+            self.initial_ref_trigger_offset <= 127,
+            "Initial reference trigger offset must be at most 127 NTU"
+        );
+        assert!(
+            self.tur_numerator >= 0x1_0000 && self.tur_numerator <= 0x1_FFFF,
+            "TUR numerator must be 0x1_0000 to 0x1_FFFF"
+        );
+        assert!(
+            self.tur_denominator >= 1 && self.tur_denominator <= 0x3FFF,
+            "TUR denominator must be 1 to 0x3FFF"
+        );
+        self
+    }
+}
+
+/// Error level of the TTCAN state machine
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TtErrorLevel {
+    /// S0, no error
+    NoError,
+    /// S1, warning
+    Warning,
+    /// S2, error
+    Error,
+    /// S3, severe error
+    SevereError,
+}
+
+/// Time master state of the node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MasterState {
+    /// Master_Off, no master properties relevant
+    Off,
+    /// Operating as time slave
+    Slave,
+    /// Operating as backup time master
+    BackupMaster,
+    /// Operating as current time master
+    CurrentMaster,
+}
+
+/// Synchronisation state of the node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncState {
+    /// Not synchronised to the TTCAN communication
+    OutOfSync,
+    /// Synchronising to the TTCAN communication
+    Synchronizing,
+    /// Schedule suspended by a gap
+    InGap,
+    /// Synchronised to the schedule
+    InSchedule,
+}
+
+/// TTCAN operation status
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TtStatus {
+    /// Error level
+    pub error_level: TtErrorLevel,
+    /// Time master state
+    pub master_state: MasterState,
+    /// Synchronisation state
+    pub sync_state: SyncState,
+    /// Offset of the actual to the configured reference trigger of the time master, in NTU
+    pub reference_trigger_offset: i8,
+    /// Priority of the current time master
+    pub time_master_priority: u8,
+    /// The application watchdog was not served in time
+    pub application_watchdog_event: bool,
+}
+
+bitflags::bitflags! {
+    /// A set of TTCAN interrupts
+    pub struct TtInterrupts: u32 {
+        /// Start of a basic cycle
+        const START_OF_BASIC_CYCLE = 1 << 0;
+        /// Start of a matrix cycle
+        const START_OF_MATRIX_CYCLE = 1 << 1;
+        /// The synchronisation or master state changed
+        const SYNC_MODE_CHANGED = 1 << 2;
+        /// Start of a gap
+        const START_OF_GAP = 1 << 3;
+        /// The register time mark was reached
+        const REGISTER_TIME_MARK = 1 << 4;
+        /// A trigger with a time mark event became active
+        const TRIGGER_TIME_MARK = 1 << 5;
+        /// The stop watch captured a time
+        const STOP_WATCH = 1 << 6;
+        /// The global time wrapped around
+        const GLOBAL_TIME_WRAP = 1 << 7;
+        /// Discontinuity of the global time
+        const GLOBAL_TIME_DISCONTINUITY = 1 << 8;
+        /// Global time error
+        const GLOBAL_TIME_ERROR = 1 << 9;
+        /// Fewer Tx triggers than expected in a matrix cycle
+        const TX_COUNT_UNDERFLOW = 1 << 10;
+        /// More Tx triggers than expected in a matrix cycle
+        const TX_COUNT_OVERFLOW = 1 << 11;
+        /// Scheduling error 1
+        const SCHEDULING_ERROR_1 = 1 << 12;
+        /// Scheduling error 2
+        const SCHEDULING_ERROR_2 = 1 << 13;
+        /// The error level changed
+        const ERROR_LEVEL_CHANGED = 1 << 14;
+        /// Initialisation watch trigger reached
+        const INIT_WATCH_TRIGGER = 1 << 15;
+        /// Watch trigger reached
+        const WATCH_TRIGGER = 1 << 16;
+        /// The application watchdog was not served in time
+        const APPLICATION_WATCHDOG = 1 << 17;
+        /// The trigger memory or the TT configuration is invalid
+        const CONFIGURATION_ERROR = 1 << 18;
+    }
+}
+
+impl<I> FdCan<I, ConfigMode>
+where
+    I: TtInstance,
+{
+    /// Configures level 1 time-triggered operation with `config` and writes `schedule` to the
+    /// trigger memory. Time-triggered operation starts when leaving ConfigMode.
+    ///
+    /// The expected number of Tx triggers in a matrix cycle is derived from `schedule`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config` or `schedule` are invalid, see [`TtConfig::check`] and
+    /// [`check_schedule`], or if `schedule` does not fit into the trigger memory of the Message
+    /// RAM layout.
+    pub fn set_time_triggered(&mut self, config: TtConfig, schedule: &[Trigger]) {
+        let config = config.check();
+        check_schedule(schedule);
+        let layout: MessageRamLayout = self.control.config.message_ram_layout;
+        assert!(
+            schedule.len() <= layout.trigger_memory as usize,
+            "Schedule does not fit into the trigger memory"
+        );
+
+        let can = self.registers();
+        let (rid, xtd) = match config.reference_id {
+            Id::Standard(id) => ((id.as_raw() as u32) << 18, false),
+            Id::Extended(id) => (id.as_raw(), true),
+        };
+        can.ttrmc.write(|w| unsafe {
+            w.rid()
+                .bits(rid)
+                .xtd()
+                .bit(xtd)
+                .rmps()
+                .bit(config.reference_payload)
+        });
+        can.ttocf.write(|w| unsafe {
+            w.om()
+                .bits(0b01)
+                .tm()
+                .bit(config.time_master)
+                .irto()
+                .bits(config.initial_ref_trigger_offset)
+                .awl()
+                .bits(config.application_watchdog_limit)
+        });
+        can.ttmlm.write(|w| unsafe {
+            w.ccm()
+                .bits(config.matrix_cycles - 1)
+                .txew()
+                .bits(config.tx_enable_window - 1)
+                .entt()
+                .bits(expected_tx_triggers(schedule, config.matrix_cycles))
+        });
+
+        // The time unit ratio can only be changed while the local time is stopped. DC stays
+        // locked until the cleared ELT is synchronised into the CAN clock domain.
+        can.turcf.modify(|_, w| w.elt().clear_bit());
+        while can.turcf.read().elt().bit_is_set() {}
+        can.turcf.write(|w| unsafe {
+            w.ncl()
+                .bits(config.tur_numerator as u16)
+                .dc()
+                .bits(config.tur_denominator)
+        });
+        can.turcf.modify(|_, w| w.elt().set_bit());
+
+        can.tttmc.write(|w| unsafe {
+            w.tmsa()
+                .bits(layout.trigger_memory_start())
+                .tme()
+                .bits(schedule.len() as u8)
+        });
+        for (i, trigger) in schedule.iter().enumerate() {
+            let [t0, t1] = trigger.words();
+            unsafe {
+                let element = message_ram::trigger_memory_element::<I>(i as u8);
+                ptr::write_volatile(element, t0);
+                ptr::write_volatile(element.add(1), t1);
+            }
+        }
+    }
+
+    /// Returns to event-driven operation
+    pub fn set_event_driven(&mut self) {
+        let can = self.registers();
+        can.ttocf.modify(|_, w| unsafe { w.om().bits(0b00) });
+        can.tttmc.write(|w| unsafe { w.tme().bits(0) });
+    }
+}
+
+impl<I, MODE> FdCanControl<I, MODE>
+where
+    I: TtInstance,
+{
+    /// Returns the local time in NTU
+    #[inline]
+    pub fn local_time(&self) -> u16 {
+        self.registers().ttlgt.read().lt().bits()
+    }
+
+    /// Returns the global time in NTU
+    #[inline]
+    pub fn global_time(&self) -> u16 {
+        self.registers().ttlgt.read().gt().bits()
+    }
+
+    /// Returns the time since the start of the basic cycle in NTU
+    #[inline]
+    pub fn cycle_time(&self) -> u16 {
+        self.registers().ttctc.read().ct().bits()
+    }
+
+    /// Returns the number of the current basic cycle in the matrix cycle
+    #[inline]
+    pub fn cycle_count(&self) -> u8 {
+        self.registers().ttctc.read().cc().bits()
+    }
+
+    /// Returns the TTCAN operation status. This also serves the application watchdog.
+    #[inline]
+    pub fn tt_status(&self) -> TtStatus {
+        let ttost = self.registers().ttost.read();
+        TtStatus {
+            error_level: match ttost.el().bits() {
+                0b00 => TtErrorLevel::NoError,
+                0b01 => TtErrorLevel::Warning,
+                0b10 => TtErrorLevel::Error,
+                _ => TtErrorLevel::SevereError,
+            },
+            master_state: match ttost.ms().bits() {
+                0b00 => MasterState::Off,
+                0b01 => MasterState::Slave,
+                0b10 => MasterState::BackupMaster,
+                _ => MasterState::CurrentMaster,
+            },
+            sync_state: match ttost.sys().bits() {
+                0b00 => SyncState::OutOfSync,
+                0b01 => SyncState::Synchronizing,
+                0b10 => SyncState::InGap,
+                _ => SyncState::InSchedule,
+            },
+            reference_trigger_offset: ttost.rto().bits() as i8,
+            time_master_priority: ttost.tmp().bits(),
+            application_watchdog_event: ttost.awe().bit_is_set(),
+        }
+    }
+
+    /// Returns the pending TTCAN interrupts
+    #[inline]
+    pub fn tt_interrupts(&self) -> TtInterrupts {
+        TtInterrupts::from_bits_truncate(self.registers().ttir.read().bits())
+    }
+
+    /// Clears the given TTCAN interrupts
+    #[inline]
+    pub fn clear_tt_interrupts(&mut self, interrupts: TtInterrupts) {
+        self.registers()
+            .ttir
+            .write(|w| unsafe { w.bits(interrupts.bits()) });
+    }
+}
+
+impl<I, MODE> FdCan<I, MODE>
+where
+    I: TtInstance,
+{
+    /// Returns the local time in NTU
+    #[inline]
+    pub fn local_time(&self) -> u16 {
+        self.control.local_time()
+    }
+
+    /// Returns the global time in NTU
+    #[inline]
+    pub fn global_time(&self) -> u16 {
+        self.control.global_time()
+    }
+
+    /// Returns the time since the start of the basic cycle in NTU
+    #[inline]
+    pub fn cycle_time(&self) -> u16 {
+        self.control.cycle_time()
+    }
+
+    /// Returns the number of the current basic cycle in the matrix cycle
+    #[inline]
+    pub fn cycle_count(&self) -> u8 {
+        self.control.cycle_count()
+    }
+
+    /// Returns the TTCAN operation status. This also serves the application watchdog.
+    #[inline]
+    pub fn tt_status(&self) -> TtStatus {
+        self.control.tt_status()
+    }
+
+    /// Returns the pending TTCAN interrupts
+    #[inline]
+    pub fn tt_interrupts(&self) -> TtInterrupts {
+        self.control.tt_interrupts()
+    }
+
+    /// Clears the given TTCAN interrupts
+    #[inline]
+    pub fn clear_tt_interrupts(&mut self, interrupts: TtInterrupts) {
+        self.control.clear_tt_interrupts(interrupts)
+    }
+
+    /// Starts listening for a set of TTCAN interrupts
+    #[inline]
+    pub fn enable_tt_interrupts(&mut self, interrupts: TtInterrupts) {
+        self.registers()
+            .ttie
+            .modify(|r, w| unsafe { w.bits(r.bits() | interrupts.bits()) })
+    }
+
+    /// Stops listening for a set of TTCAN interrupts
+    #[inline]
+    pub fn disable_tt_interrupts(&mut self, interrupts: TtInterrupts) {
+        self.registers()
+            .ttie
+            .modify(|r, w| unsafe { w.bits(r.bits() & !interrupts.bits()) })
+    }
+
+    /// Selects Interrupt Line 1 for the given TTCAN interrupts. Interrupt Line 0 is selected
+    /// for all other TTCAN interrupts.
+    #[inline]
+    pub fn select_tt_interrupt_line_1(&mut self, l1int: TtInterrupts) {
+        self.registers()
+            .ttils
+            .write(|w| unsafe { w.bits(l1int.bits()) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_words() {
+        let t = Trigger::tx_single(200, Mailbox::_1)
+            .set_cycle(2, 1)
+            .set_time_mark_event(true);
+        // TM[31:16], CC[14:8], TMIN[7], TYPE[3:0] and MSC[22:16]
+        assert_eq!(t.words(), [200 << 16 | 3 << 8 | 1 << 7 | 0b0010, 1 << 16]);
+
+        let t = Trigger::rx_extended(0xffff, ExtendedFilterSlot::_5);
+        assert_eq!(
+            t.words(),
+            [0xffff << 16 | 1 << 8 | 0b1000, 1 << 23 | 5 << 16]
+        );
+
+        let t = Trigger::watch(900).set_type(TriggerType::WatchTriggerGap);
+        assert_eq!(t.words(), [900 << 16 | 1 << 8 | 0b0111, 0]);
+    }
+
+    #[test]
+    fn cycle_code() {
+        let t = Trigger::time_base(0);
+        assert_eq!((t.cycle_code, t.repeat()), (1, 1));
+        let t = t.set_cycle(4, 3);
+        assert_eq!((t.cycle_code, t.repeat()), (7, 4));
+        let t = t.set_cycle(64, 63);
+        assert_eq!((t.cycle_code, t.repeat()), (127, 64));
+        let t = t.set_cycle(64, 0);
+        assert_eq!((t.cycle_code, t.repeat()), (64, 64));
+    }
+
+    #[test]
+    #[should_panic(expected = "Repeat factor must be a power of two up to 64")]
+    fn cycle_repeat_not_power_of_two() {
+        Trigger::time_base(0).set_cycle(3, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Cycle offset must be smaller than the repeat factor")]
+    fn cycle_offset_too_large() {
+        Trigger::time_base(0).set_cycle(4, 4);
+    }
+
+    #[test]
+    fn tx_triggers_per_matrix_cycle() {
+        let schedule = [
+            Trigger::tx_ref(0),
+            Trigger::tx_single(100, Mailbox::_1),
+            Trigger::tx_continuous(200, Mailbox::_2).set_cycle(2, 1),
+            Trigger::tx_arbitration(300, Mailbox::_3).set_cycle(4, 0),
+            Trigger::tx_merged(300, Mailbox::_4).set_cycle(8, 5),
+            Trigger::rx_standard(400, StandardFilterSlot::_0),
+            Trigger::watch(900),
+        ];
+        check_schedule(&schedule);
+        assert_eq!(expected_tx_triggers(&schedule, 1), 2);
+        assert_eq!(expected_tx_triggers(&schedule, 4), 4 + 2 + 1);
+        assert_eq!(expected_tx_triggers(&schedule, 8), 8 + 4 + 2 + 1);
+        assert_eq!(expected_tx_triggers(&schedule[5..], 8), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Triggers must be sorted by time mark")]
+    fn schedule_not_sorted() {
+        check_schedule(&[Trigger::tx_ref(100), Trigger::watch(50)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "At most 64 triggers")]
+    fn schedule_too_long() {
+        check_schedule(&[Trigger::time_base(0); 65]);
+    }
+}